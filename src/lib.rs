@@ -0,0 +1,3476 @@
+pub use encoding_rs::Encoding;
+
+use chrono::format::{ParseErrorKind, Parsed, StrftimeItems, parse};
+use flate2::read::MultiGzDecoder;
+use lazy_static::lazy_static;
+use regex::{NoExpand, Regex};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+lazy_static! {
+    // Hex addresses like 0x104fc4000 or 0x1a377d770
+    static ref HEX_ADDR: Regex = Regex::new(r"0x[a-fA-F0-9]+").unwrap();
+    // Bracketed addresses like [0x106111f74]
+    static ref BRACKETED_HEX: Regex = Regex::new(r"\[0x[a-fA-F0-9]+\]").unwrap();
+    // Parenthesized or braced addresses like (0x106111f74) or {0x106111f74}
+    static ref WRAPPED_HEX: Regex = Regex::new(r"\((0x[a-fA-F0-9]+)\)|\{(0x[a-fA-F0-9]+)\}").unwrap();
+    // UUIDs like <4B0BCBB4-2271-376E-B5C3-CC18D418FC11>
+    static ref UUID_PATTERN: Regex = Regex::new(r"<[A-F0-9]{8}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{12}>").unwrap();
+    // IPv4 addresses like 192.168.0.1, for --redact
+    static ref IPV4: Regex = Regex::new(r"\b(?:\d{1,3}\.){3}\d{1,3}\b").unwrap();
+    // Email addresses like jdoe@example.com, for --redact
+    static ref EMAIL: Regex = Regex::new(r"\b[\w.+-]+@[\w-]+(?:\.[\w-]+)+\b").unwrap();
+    // UUIDs in any case, bracketed or not
+    static ref ANY_UUID: Regex = Regex::new(r"\b[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}\b").unwrap();
+    // Phone numbers like +1-555-123-4567, (555) 123-4567 or 555-123-4567; bare E.164 numbers
+    // like +15551234567 need a tel: or phone label (group 1) so signed counters stay numbers
+    static ref PHONE_PATTERN: Regex = Regex::new(r"(?:\+\d{1,3}[-. ]?)?(?:\(\d{3}\) ?|\b\d{3}[-.])\d{3}[-.]\d{4}\b|(\b(?i:tel:|phone[:=]? ?))\+\d{8,15}\b").unwrap();
+    // Brace-wrapped GUIDs like {550e8400-e29b-41d4-a716-446655440000}, as in Windows event logs
+    static ref BRACE_GUID: Regex = Regex::new(r"\{[0-9A-Fa-f]{8}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{4}-[0-9A-Fa-f]{12}\}").unwrap();
+    // Windows event IDs like EventID=4624, which name the kind of event and so stay literal
+    static ref EVENT_ID: Regex = Regex::new(r"(?i)\bevent_?id[=:]\s?$").unwrap();
+    // Candidate random IDs like a1b2c3d4e5 or Xk7Qp2Lm9 (must mix letters and digits)
+    static ref MIXED_ID: Regex = Regex::new(r"\b[A-Za-z0-9]{8,}\b").unwrap();
+    // Case-shaped pieces of identifiers, like Log, Config and Loader in Log4jConfigLoader
+    static ref IDENT_PIECE: Regex = Regex::new(r"[A-Z][a-z]+|[a-z]+|[A-Z]+").unwrap();
+    // Non-empty double-quoted strings, honoring backslash escapes
+    static ref QUOTED: Regex = Regex::new(r#""(?:[^"\\]|\\.)+""#).unwrap();
+    // Windows paths like C:\Users\alice\x.tmp or \\server\share\x; rootless ones like
+    // \Windows\Temp are left alone, since they look just like escaped text (line1\nline2)
+    static ref WIN_PATH: Regex = Regex::new(r#"\b[A-Za-z]:(?:\\[^\\\s"<>|]*)+|\\\\[^\\\s"<>|]+(?:\\[^\\\s"<>|]+)+"#).unwrap();
+    // Colon-separated fields like `code: 111` or `msg: "peer gone"`, valued by one token or
+    // one quoted string, so a `kernel: Out of memory` label keeps the rest of its message
+    static ref KV_COLON: Regex = Regex::new(r#"(^|[\s,;{])([a-z_][a-z0-9_.]*): (?:"(?:[^"\\]|\\.)*"|[^\s,;}]+)"#).unwrap();
+    // Unix and URL paths like /api/v2/users or https://host/api/v2, capturing what precedes them
+    static ref URL_PATH: Regex = Regex::new(r#"(^|[\s"'=(]|://[^/\s]+)(/[^\s"'<>()]+)"#).unwrap();
+    // Latitude/longitude pairs like 37.7749,-122.4194 (ranges checked on replacement)
+    static ref COORD_PATTERN: Regex = Regex::new(r"-?\b(\d{1,2}\.\d{3,}),\s?(-?\d{1,3}\.\d{3,})\b").unwrap();
+    // A number list going on before or after a candidate coordinate pair
+    static ref LIST_BEFORE: Regex = Regex::new(r"\d,\s?$").unwrap();
+    static ref LIST_AFTER: Regex = Regex::new(r"^,\s?-?\d").unwrap();
+    // Monetary amounts like $1,234.56, €99.00, USD 42.00 or 42.00 EUR
+    static ref MONEY_PATTERN: Regex = Regex::new(concat!(
+        r"[$€£¥]\s?\d+(?:,\d{3})*(?:\.\d+)?",
+        r"|\b(?:USD|EUR|GBP|JPY|CHF|CAD|AUD|CNY|INR)\s?\d+(?:,\d{3})*(?:\.\d+)?\b",
+        r"|\b\d+(?:,\d{3})*(?:\.\d+)?\s?(?:USD|EUR|GBP|JPY|CHF|CAD|AUD|CNY|INR|€)",
+    )).unwrap();
+    // Semantic versions like 1.2.3, v10.4.0-rc1 or 2.0.0+build.7 (dotted runs
+    // of any length are matched so IPv4 addresses can be told apart)
+    static ref SEMVER_PATTERN: Regex = Regex::new(r"\bv?\d+(?:\.\d+){2,}(?:-[0-9A-Za-z]+(?:[.-][0-9A-Za-z]+)*)?(?:\+[0-9A-Za-z]+(?:\.[0-9A-Za-z]+)*)?").unwrap();
+    // Bracketed log levels like [INFO], [ warn ] or (DEBUG), in any case or padding
+    static ref BRACKETED_LEVEL: Regex = Regex::new(r"(?i)([\[(])\s*(trace|debug|info|notice|warn|warning|error|err|crit|critical|fatal|alert|emerg)\s*([\])])").unwrap();
+    // Container IDs (64 or 12 hex digits) after docker://-style schemes or a container label
+    static ref CONTAINER_ID: Regex = Regex::new(r"(?i)((?:docker|containerd|cri-o)://|\bcontainer(?:[ _]?id)?[ =:]\s*)(?:[0-9a-f]{64}|[0-9a-f]{12})\b").unwrap();
+    // Deployment pod names like app-7d9f8b6c4-xk2lm, whose suffixes use Kubernetes' vowel-free alphabet
+    static ref POD_NAME: Regex = Regex::new(r"\b([a-z][a-z0-9-]*?)-[bcdfghjklmnpqrstvwxz2-9]{6,10}-[bcdfghjklmnpqrstvwxz2-9]{5}\b").unwrap();
+    // Bare hex digests; only MD5, SHA-1 and SHA-256 lengths are normalized
+    static ref HASH_PATTERN: Regex = Regex::new(r"\b[a-fA-F0-9]{32,64}\b").unwrap();
+    // Candidate base64 blobs like dGhpcyBpcyBhIHRlc3Q= (checked further in normalize_base64)
+    static ref BASE64_PATTERN: Regex = Regex::new(r"[A-Za-z0-9+/]{16,}={0,2}").unwrap();
+    // Process IDs like sshd[24245] or su(pam_unix)[21416]; single digits only
+    // count with the syslog `:` after them, so indexing like args[0] stays literal
+    static ref PROCESS_ID: Regex = Regex::new(r"\b([A-Za-z_][\w.-]*(?:\([^()\s]*\))?)\[(\d+)\](:?)").unwrap();
+    // Thread IDs like Thread_4243153
+    static ref THREAD_ID: Regex = Regex::new(r"Thread_\d+").unwrap();
+    // Labeled thread IDs like [thread-42] or goroutine 1234
+    static ref LABELED_THREAD: Regex = Regex::new(r"(\[thread-)\d+\]|\b(goroutine )\d+\b").unwrap();
+    // Timestamps like 07:28:03 or 22:18:29.360
+    static ref TIMESTAMP: Regex = Regex::new(r"\b\d{2}:\d{2}:\d{2}(?:\.\d+)?").unwrap();
+    // Numbers with thousands separators like 1,234,567
+    static ref GROUPED_NUM: Regex = Regex::new(r"\b\d{1,3}(?:,\d{3})+\b").unwrap();
+    // Ordinals like 1st, 22nd or 103rd
+    static ref ORDINAL: Regex = Regex::new(r"\b\d+(?:st|nd|rd|th)\b").unwrap();
+    // Large numbers (5+ digits) that are likely variable identifiers
+    static ref LARGE_NUM: Regex = Regex::new(r"\b\d{5,}\b").unwrap();
+    // Durations like 123ms, 1.5s or 2m30s
+    static ref DURATION_PATTERN: Regex = Regex::new(r"\b(?:\d+(?:\.\d+)?(?:ns|us|µs|ms|h|m|s))+\b").unwrap();
+    // Epoch seconds or milliseconds between 2001 and 2033 (length-based guess)
+    static ref EPOCH: Regex = Regex::new(r"\b1\d{9}(?:\d{3})?\b").unwrap();
+    // Signed large numbers like -123456 or +54087, only at the start of a field
+    static ref SIGNED_NUM: Regex = Regex::new(r"(^|[\s(\[=,:])[+-](\d{5,})\b").unwrap();
+    // Numbers of any length, for --all-numbers-variable
+    static ref ANY_NUM: Regex = Regex::new(r"\b\d+\b").unwrap();
+    static ref SIGNED_ANY_NUM: Regex = Regex::new(r"(^|[\s(\[=,:])[+-](\d+)\b").unwrap();
+    // ANSI escape sequences: CSI (colors, cursor moves) and OSC (titles, links)
+    static ref ANSI_ESCAPE: Regex = Regex::new(r"\x1b\[[0-?]*[ -/]*[@-~]|\x1b\][^\x07\x1b]*(?:\x07|\x1b\\)").unwrap();
+    // Whitespace-separated fields
+    static ref FIELD: Regex = Regex::new(r"\S+").unwrap();
+    // Runs of spaces and tabs between fields
+    static ref WHITESPACE_RUN: Regex = Regex::new(r"\s+").unwrap();
+    // Indentation pattern: leading whitespace and tree markers
+    static ref INDENT_PATTERN: Regex = Regex::new(r"^([\s+!|:]+)").unwrap();
+    // Binary image line pattern (macOS sample/crash reports)
+    static ref BINARY_IMAGE: Regex = Regex::new(r"^\s*0x[a-fA-F0-9]+\s+-\s+0x[a-fA-F0-9]+\s+").unwrap();
+    // System library paths
+    static ref SYSTEM_LIB: Regex = Regex::new(r"/System/Library/|/usr/lib/").unwrap();
+}
+
+/// Command-line options controlling normalization and output
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Collapse embedded `{...}` JSON fragments to `<json>`
+    pub normalize_json: bool,
+    /// Print a `# N lines -> M templates` header before the groups
+    pub summary: bool,
+    /// Render `<num>` as `<num:N>` when every occurrence has N digits
+    pub num_width: bool,
+    /// User-supplied `(pattern, placeholder)` rules applied before the built-ins
+    pub rules: RuleSet,
+    /// Only collapse exact duplicate lines, skipping all normalization
+    pub dedup: bool,
+    /// Treat 10- and 13-digit numbers starting with `1` as `<epoch>` timestamps
+    pub epoch: bool,
+    /// Print the original lines whose template occurred only once
+    pub invert: bool,
+    /// Prefix each template with a stable `[id=xxxxxxxx]` hash
+    pub ids: bool,
+    /// Collapse double-quoted strings to `<str>`
+    pub normalize_quoted: bool,
+    /// Delimiters wrapped around rendered placeholder names
+    pub delimiters: Delimiters,
+    /// Number of leading whitespace-separated fields to keep literal
+    pub literal_prefix: usize,
+    /// Output format
+    pub format: Format,
+    /// Truncate lines longer than this many characters, marking them `<truncated>`
+    pub max_line_length: Option<usize>,
+    /// Keep each template's most common leading indentation
+    pub preserve_spacing: bool,
+    /// Print the first original line of each group, indented below its template
+    pub example: bool,
+    /// First input line to process (1-based, inclusive)
+    pub from: Option<usize>,
+    /// Last input line to process (1-based, inclusive)
+    pub to: Option<usize>,
+    /// Replace numbers of any length with `<num>`, not just 5+ digit ones
+    pub all_numbers_variable: bool,
+    /// Append a block with the reduction ratio and variables per template
+    pub metrics: bool,
+    /// Remove ANSI color and control escape sequences before processing
+    pub strip_ansi: bool,
+    /// Print only the number of templates
+    pub count_only: bool,
+    /// Exact field replacements (literal -> placeholder) applied before any pattern
+    pub replacements: HashMap<String, String>,
+    /// Keep only this many leading path segments, collapsing the rest to `<path-tail>`
+    pub path_depth: Option<usize>,
+    /// Order of the groups in the output
+    pub sort: SortKey,
+    /// Reverse the output order
+    pub reverse: bool,
+    /// Show the source line numbers of each template (the first `MAX_LINE_NUMBERS`)
+    pub line_numbers: bool,
+    /// Treat the value of every lowercase `key: value` field as variable
+    pub kv_colon: bool,
+    /// Value kinds replaced with `<redacted>` before anything else, so no output shows them
+    pub redact: Vec<Sensitive>,
+    /// Append a bar proportional to each group's count
+    pub bars: bool,
+    /// Collapse bracketed numeric lists like `[1, 2, 3]` to `<array>`
+    pub normalize_arrays: bool,
+    /// Only show the group whose template is exactly this; nothing if none is
+    pub matching: Option<String>,
+    /// Extra timestamp format normalized to `<time>`
+    pub date_format: Option<DateFormat>,
+    /// Drop trailing `,` `;` `.` `!` `?` from fields so `root,` groups with `root`
+    pub trim_punct: bool,
+    /// Parse each line as JSON and compact only this string field
+    pub json_field: Option<String>,
+    /// Skip every built-in pattern, leaving `replacements`, `rules` and the enabled opt-in
+    /// detectors (`normalize_json`, `kv_colon`, `epoch`, ...)
+    pub no_inherent: bool,
+    /// Fields forced to a placeholder by position, before any other normalization
+    pub column_rules: Vec<ColumnRule>,
+    /// Show the sorted groups this many at a time
+    pub page_size: Option<usize>,
+    /// Which page to show (1-based) when `page_size` is set
+    pub page: usize,
+    /// Expand literal `\n` and `\t` first, so embedded payloads become separate lines
+    pub unescape: bool,
+    /// Number each placeholder by position in its template, e.g. `<0:ip> port <1:num>`
+    pub annotate_types: bool,
+}
+
+/// Fluent setters, one per field, for building options in library code:
+///
+/// ```
+/// use comprende::{Format, Options, compact};
+///
+/// let opts = Options::default()
+///     .with_summary(true)
+///     .with_ids(false)
+///     .with_max_line_length(200)
+///     .with_format(Format::Text);
+///
+/// let input = "job 48213 done\njob 51877 done";
+/// assert_eq!(compact(input, &opts), "# 2 lines -> 1 templates\n[2x] job <num> done");
+/// ```
+impl Options {
+    pub fn with_normalize_json(mut self, enabled: bool) -> Self {
+        self.normalize_json = enabled;
+        self
+    }
+
+    pub fn with_summary(mut self, enabled: bool) -> Self {
+        self.summary = enabled;
+        self
+    }
+
+    pub fn with_num_width(mut self, enabled: bool) -> Self {
+        self.num_width = enabled;
+        self
+    }
+
+    pub fn with_rules(mut self, rules: RuleSet) -> Self {
+        self.rules = rules;
+        self
+    }
+
+    pub fn with_dedup(mut self, enabled: bool) -> Self {
+        self.dedup = enabled;
+        self
+    }
+
+    pub fn with_epoch(mut self, enabled: bool) -> Self {
+        self.epoch = enabled;
+        self
+    }
+
+    pub fn with_invert(mut self, enabled: bool) -> Self {
+        self.invert = enabled;
+        self
+    }
+
+    pub fn with_ids(mut self, enabled: bool) -> Self {
+        self.ids = enabled;
+        self
+    }
+
+    pub fn with_normalize_quoted(mut self, enabled: bool) -> Self {
+        self.normalize_quoted = enabled;
+        self
+    }
+
+    pub fn with_delimiters(mut self, open: &str, close: &str) -> Self {
+        self.delimiters = Delimiters {
+            open: open.to_string(),
+            close: close.to_string(),
+        };
+        self
+    }
+
+    pub fn with_literal_prefix(mut self, value: usize) -> Self {
+        self.literal_prefix = value;
+        self
+    }
+
+    pub fn with_format(mut self, format: Format) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_max_line_length(mut self, value: usize) -> Self {
+        self.max_line_length = Some(value);
+        self
+    }
+
+    pub fn with_preserve_spacing(mut self, enabled: bool) -> Self {
+        self.preserve_spacing = enabled;
+        self
+    }
+
+    pub fn with_example(mut self, enabled: bool) -> Self {
+        self.example = enabled;
+        self
+    }
+
+    pub fn with_from(mut self, value: usize) -> Self {
+        self.from = Some(value);
+        self
+    }
+
+    pub fn with_to(mut self, value: usize) -> Self {
+        self.to = Some(value);
+        self
+    }
+
+    pub fn with_all_numbers_variable(mut self, enabled: bool) -> Self {
+        self.all_numbers_variable = enabled;
+        self
+    }
+
+    pub fn with_metrics(mut self, enabled: bool) -> Self {
+        self.metrics = enabled;
+        self
+    }
+
+    pub fn with_strip_ansi(mut self, enabled: bool) -> Self {
+        self.strip_ansi = enabled;
+        self
+    }
+
+    pub fn with_count_only(mut self, enabled: bool) -> Self {
+        self.count_only = enabled;
+        self
+    }
+
+    pub fn with_replacements(mut self, replacements: HashMap<String, String>) -> Self {
+        self.replacements = replacements;
+        self
+    }
+
+    pub fn with_path_depth(mut self, value: usize) -> Self {
+        self.path_depth = Some(value);
+        self
+    }
+
+    pub fn with_sort(mut self, sort: SortKey) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    pub fn with_reverse(mut self, enabled: bool) -> Self {
+        self.reverse = enabled;
+        self
+    }
+
+    pub fn with_line_numbers(mut self, enabled: bool) -> Self {
+        self.line_numbers = enabled;
+        self
+    }
+
+    pub fn with_kv_colon(mut self, enabled: bool) -> Self {
+        self.kv_colon = enabled;
+        self
+    }
+
+    pub fn with_redact(mut self, redact: Vec<Sensitive>) -> Self {
+        self.redact = redact;
+        self
+    }
+
+    pub fn with_bars(mut self, enabled: bool) -> Self {
+        self.bars = enabled;
+        self
+    }
+
+    pub fn with_normalize_arrays(mut self, enabled: bool) -> Self {
+        self.normalize_arrays = enabled;
+        self
+    }
+
+    pub fn with_matching(mut self, template: &str) -> Self {
+        self.matching = Some(template.to_string());
+        self
+    }
+
+    pub fn with_date_format(mut self, format: DateFormat) -> Self {
+        self.date_format = Some(format);
+        self
+    }
+
+    pub fn with_trim_punct(mut self, enabled: bool) -> Self {
+        self.trim_punct = enabled;
+        self
+    }
+
+    pub fn with_json_field(mut self, field: &str) -> Self {
+        self.json_field = Some(field.to_string());
+        self
+    }
+
+    pub fn with_no_inherent(mut self, enabled: bool) -> Self {
+        self.no_inherent = enabled;
+        self
+    }
+
+    pub fn with_column_rules(mut self, rules: Vec<ColumnRule>) -> Self {
+        self.column_rules = rules;
+        self
+    }
+
+    pub fn with_page_size(mut self, value: usize) -> Self {
+        self.page_size = Some(value);
+        self
+    }
+
+    pub fn with_page(mut self, value: usize) -> Self {
+        self.page = value;
+        self
+    }
+
+    pub fn with_unescape(mut self, enabled: bool) -> Self {
+        self.unescape = enabled;
+        self
+    }
+
+    pub fn with_annotate_types(mut self, enabled: bool) -> Self {
+        self.annotate_types = enabled;
+        self
+    }
+}
+
+/// Output format of `process` and `Compactor::snapshot`
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Human-readable `[Nx] template` lines
+    #[default]
+    Text,
+    /// One `count=N<TAB>template=...` line per group, see `escape_kv`
+    Kv,
+    /// A GitHub-flavored `Count | Template | Sample` table, see `markdown_cell`
+    Markdown,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "kv" => Ok(Format::Kv),
+            "markdown" => Ok(Format::Markdown),
+            _ => Err(format!("unknown format: {}", s)),
+        }
+    }
+}
+
+/// Order of the groups in the output
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    /// Most frequent templates first
+    #[default]
+    Count,
+    /// Templates in lexicographic order
+    Template,
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "count" => Ok(SortKey::Count),
+            "template" => Ok(SortKey::Template),
+            _ => Err(format!("unknown sort key: {}", s)),
+        }
+    }
+}
+
+/// Kinds of sensitive values `--redact` removes from templates and samples
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sensitive {
+    Ip,
+    Email,
+    Uuid,
+    Phone,
+}
+
+impl Sensitive {
+    fn pattern(self) -> &'static Regex {
+        match self {
+            Sensitive::Ip => &IPV4,
+            Sensitive::Email => &EMAIL,
+            Sensitive::Uuid => &ANY_UUID,
+            Sensitive::Phone => &PHONE_PATTERN,
+        }
+    }
+}
+
+impl FromStr for Sensitive {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ip" => Ok(Sensitive::Ip),
+            "email" => Ok(Sensitive::Email),
+            "uuid" => Ok(Sensitive::Uuid),
+            "phone" => Ok(Sensitive::Phone),
+            _ => Err(format!("unknown redact type: {}", s)),
+        }
+    }
+}
+
+/// A language whose month names `%b` and `%B` accept, besides English
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DateLocale {
+    De,
+    Es,
+    Fr,
+    It,
+    Nl,
+    Pt,
+}
+
+const ENGLISH_MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+impl DateLocale {
+    /// Lowercase full and abbreviated names of each month, January first
+    fn months(self) -> [&'static [&'static str]; 12] {
+        match self {
+            DateLocale::De => [
+                &["januar", "jänner", "jan", "jän"],
+                &["februar", "feb"],
+                &["märz", "mär", "mrz"],
+                &["april", "apr"],
+                &["mai"],
+                &["juni", "jun"],
+                &["juli", "jul"],
+                &["august", "aug"],
+                &["september", "sept", "sep"],
+                &["oktober", "okt"],
+                &["november", "nov"],
+                &["dezember", "dez"],
+            ],
+            DateLocale::Es => [
+                &["enero", "ene"],
+                &["febrero", "feb"],
+                &["marzo", "mar"],
+                &["abril", "abr"],
+                &["mayo", "may"],
+                &["junio", "jun"],
+                &["julio", "jul"],
+                &["agosto", "ago"],
+                &["septiembre", "setiembre", "sept", "sep", "set"],
+                &["octubre", "oct"],
+                &["noviembre", "nov"],
+                &["diciembre", "dic"],
+            ],
+            DateLocale::Fr => [
+                &["janvier", "janv"],
+                &["février", "févr", "fév"],
+                &["mars"],
+                &["avril", "avr"],
+                &["mai"],
+                &["juin"],
+                &["juillet", "juil"],
+                &["août"],
+                &["septembre", "sept"],
+                &["octobre", "oct"],
+                &["novembre", "nov"],
+                &["décembre", "déc"],
+            ],
+            DateLocale::It => [
+                &["gennaio", "gen"],
+                &["febbraio", "feb"],
+                &["marzo", "mar"],
+                &["aprile", "apr"],
+                &["maggio", "mag"],
+                &["giugno", "giu"],
+                &["luglio", "lug"],
+                &["agosto", "ago"],
+                &["settembre", "set"],
+                &["ottobre", "ott"],
+                &["novembre", "nov"],
+                &["dicembre", "dic"],
+            ],
+            DateLocale::Nl => [
+                &["januari", "jan"],
+                &["februari", "feb"],
+                &["maart", "mrt"],
+                &["april", "apr"],
+                &["mei"],
+                &["juni", "jun"],
+                &["juli", "jul"],
+                &["augustus", "aug"],
+                &["september", "sep"],
+                &["oktober", "okt"],
+                &["november", "nov"],
+                &["december", "dec"],
+            ],
+            DateLocale::Pt => [
+                &["janeiro", "jan"],
+                &["fevereiro", "fev"],
+                &["março", "mar"],
+                &["abril", "abr"],
+                &["maio", "mai"],
+                &["junho", "jun"],
+                &["julho", "jul"],
+                &["agosto", "ago"],
+                &["setembro", "set"],
+                &["outubro", "out"],
+                &["novembro", "nov"],
+                &["dezembro", "dez"],
+            ],
+        }
+    }
+
+    /// Replace this language's month names in `text` with English abbreviations
+    fn translate_months(self, text: &str) -> String {
+        let months = self.months();
+        let mut result = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(char::is_alphabetic) {
+            result.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest.find(|c: char| !c.is_alphabetic()).unwrap_or(rest.len());
+            let word = rest[..end].to_lowercase();
+            match months.iter().position(|names| names.contains(&word.as_str())) {
+                Some(month) => result.push_str(ENGLISH_MONTHS[month]),
+                None => result.push_str(&rest[..end]),
+            }
+            rest = &rest[end..];
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+impl FromStr for DateLocale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "de" => Ok(DateLocale::De),
+            "es" => Ok(DateLocale::Es),
+            "fr" => Ok(DateLocale::Fr),
+            "it" => Ok(DateLocale::It),
+            "nl" => Ok(DateLocale::Nl),
+            "pt" => Ok(DateLocale::Pt),
+            _ => Err(format!("unknown date locale: {} (expected de, es, fr, it, nl or pt)", s)),
+        }
+    }
+}
+
+/// A strftime-style timestamp format (`%d.%m.%Y %H:%M`) recognized in addition to the
+/// built-in forms; candidates are found by a regex derived from it, then checked by chrono
+#[derive(Debug, Clone)]
+pub struct DateFormat {
+    format: String,
+    pattern: Regex,
+    locale: Option<DateLocale>,
+}
+
+impl DateFormat {
+    /// Also accept month names in `locale` (English names still parse)
+    pub fn with_locale(mut self, locale: DateLocale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    /// Whether `text` really is a date in this format, not just digits in the right places
+    fn parses(&self, text: &str) -> bool {
+        let text = match self.locale {
+            Some(locale) => locale.translate_months(text),
+            None => text.to_string(),
+        };
+        let mut parsed = Parsed::new();
+        if parse(&mut parsed, &text, StrftimeItems::new(&self.format)).is_err() {
+            return false;
+        }
+        // Formats may carry only a date or only a time, but what's there must exist
+        let possible = |result: Result<_, chrono::ParseError>| match result {
+            Ok(_) => true,
+            Err(err) => err.kind() == ParseErrorKind::NotEnough,
+        };
+        possible(parsed.to_naive_date().map(|_| ()))
+            && possible(parsed.to_naive_time().map(|_| ()))
+    }
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A word boundary only makes sense before a word; `[%d/%b/%Y...]` starts with `[`
+        let mut pattern = String::new();
+        if s.starts_with(|c: char| c == '%' || c == '_' || c.is_alphanumeric()) {
+            pattern.push_str(r"\b");
+        }
+        let mut chars = s.chars();
+
+        while let Some(c) = chars.next() {
+            if c.is_whitespace() {
+                pattern.push_str(r"\s+");
+                continue;
+            }
+            if c != '%' {
+                pattern.push_str(&regex::escape(&c.to_string()));
+                continue;
+            }
+            let piece = match chars.next() {
+                Some('Y') => r"\d{4}",
+                Some('y' | 'C') => r"\d{2}",
+                Some('m' | 'd' | 'e' | 'H' | 'I' | 'M' | 'S') => r"\d{1,2}",
+                Some('j') => r"\d{1,3}",
+                Some('f') => r"\d+",
+                Some('a') => r"\p{L}{3}",
+                // Abbreviations run longer than three letters in some languages (juil, sept)
+                Some('b' | 'h' | 'B' | 'A') => r"\p{L}+",
+                Some('p') => r"[AaPp][Mm]",
+                Some('z') => r"[+-]\d{2}:?\d{2}",
+                Some('Z') => r"[A-Za-z]+",
+                Some('T') => r"\d{2}:\d{2}:\d{2}",
+                Some('F') => r"\d{4}-\d{2}-\d{2}",
+                Some('%') => "%",
+                Some(other) => return Err(format!("unsupported date format specifier: %{}", other)),
+                None => return Err("date format ends with a lone %".to_string()),
+            };
+            pattern.push_str(piece);
+        }
+
+        Ok(DateFormat {
+            format: s.to_string(),
+            pattern: Regex::new(&pattern).map_err(|err| err.to_string())?,
+            locale: None,
+        })
+    }
+}
+
+/// Opening and closing delimiters of placeholders, `<` and `>` by default
+#[derive(Debug, Clone)]
+pub struct Delimiters {
+    pub open: String,
+    pub close: String,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters {
+            open: "<".to_string(),
+            close: ">".to_string(),
+        }
+    }
+}
+
+impl Delimiters {
+    /// Render the placeholder for `name`, e.g. `<hex>`
+    pub fn wrap(&self, name: &str) -> String {
+        format!("{}{}{}", self.open, name, self.close)
+    }
+}
+
+/// A single user-defined normalization rule from a `--rules` file
+#[derive(Debug, Deserialize)]
+struct Rule {
+    name: String,
+    regex: String,
+    placeholder: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleFile {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+/// Forces one whitespace-separated field to a placeholder: `2:time`, or
+/// `5:ip:^10\.` to only do so when the field matches a pattern
+#[derive(Debug, Clone)]
+pub struct ColumnRule {
+    /// 1-based field index
+    pub column: usize,
+    /// Placeholder name, wrapped in the delimiters when applied
+    pub placeholder: String,
+    /// Only replace fields matching this, so lines of other shapes are left alone
+    pub pattern: Option<Regex>,
+}
+
+impl FromStr for ColumnRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.splitn(3, ':');
+        let column = parts
+            .next()
+            .and_then(|idx| idx.parse().ok())
+            .filter(|&idx: &usize| idx > 0)
+            .ok_or_else(|| format!("invalid column rule {}: expected idx:type[:pattern]", s))?;
+        let placeholder = match parts.next() {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => return Err(format!("invalid column rule {}: missing type", s)),
+        };
+        let pattern = parts
+            .next()
+            .map(Regex::new)
+            .transpose()
+            .map_err(|e| format!("invalid pattern in column rule {}: {}", s, e))?;
+
+        Ok(ColumnRule {
+            column,
+            placeholder,
+            pattern,
+        })
+    }
+}
+
+/// Compiled user rules, built once and cheap to clone into any number of `Options`
+#[derive(Debug, Default, Clone)]
+pub struct RuleSet {
+    rules: Vec<(Regex, String)>,
+}
+
+impl RuleSet {
+    /// Rules from already compiled `(pattern, placeholder)` pairs, applied in order
+    pub fn new(rules: Vec<(Regex, String)>) -> Self {
+        RuleSet { rules }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Apply every rule to `line` in order
+    /// Replacement text of each rule, in order
+    pub fn placeholders(&self) -> impl Iterator<Item = &str> {
+        self.rules.iter().map(|(_, placeholder)| placeholder.as_str())
+    }
+
+    pub fn apply(&self, line: &str) -> String {
+        let mut result = line.to_string();
+        for (regex, placeholder) in &self.rules {
+            result = regex.replace_all(&result, placeholder.as_str()).to_string();
+        }
+        result
+    }
+}
+
+fn invalid_input(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg)
+}
+
+/// Parse a rules file of `[[rule]]` tables with `name`, `regex` and `placeholder`
+pub fn parse_rules(text: &str) -> io::Result<RuleSet> {
+    let file: RuleFile =
+        toml::from_str(text).map_err(|e| invalid_input(format!("invalid rules file: {}", e)))?;
+
+    file.rules
+        .into_iter()
+        .map(|rule| {
+            let regex = Regex::new(&rule.regex)
+                .map_err(|e| invalid_input(format!("invalid regex in rule {}: {}", rule.name, e)))?;
+            Ok((regex, rule.placeholder))
+        })
+        .collect::<io::Result<_>>()
+        .map(RuleSet::new)
+}
+
+/// Parse a replacement table: one `literal placeholder` pair per line,
+/// ignoring blank lines and `#` comments
+pub fn parse_replacements(text: &str) -> io::Result<HashMap<String, String>> {
+    let mut replacements = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        match fields.as_slice() {
+            [literal, placeholder] => {
+                replacements.insert(literal.to_string(), placeholder.to_string());
+            }
+            _ => {
+                return Err(invalid_input(format!(
+                    "invalid replacement on line {}: expected `literal placeholder`",
+                    i + 1
+                )));
+            }
+        }
+    }
+
+    Ok(replacements)
+}
+
+/// Replace every value of the `opts.redact` kinds with `<redacted>`
+fn redact_line(line: &str, opts: &Options) -> String {
+    let placeholder = opts.delimiters.wrap("redacted");
+    let mut result = line.to_string();
+    for kind in &opts.redact {
+        // A label captured in group 1 (like tel: before a phone number) is kept
+        result = kind
+            .pattern()
+            .replace_all(&result, |caps: &regex::Captures| {
+                format!("{}{}", caps.get(1).map_or("", |m| m.as_str()), placeholder)
+            })
+            .to_string();
+    }
+    result
+}
+
+/// Keep the first `depth` segments of `path`, replacing the rest with `tail`
+fn truncate_path(path: &str, depth: usize, tail: &str) -> String {
+    let segments: Vec<&str> = path[1..].split('/').collect();
+    if segments.len() <= depth {
+        return path.to_string();
+    }
+
+    let mut result = String::new();
+    for segment in &segments[..depth] {
+        result.push('/');
+        result.push_str(segment);
+    }
+    result.push('/');
+    result.push_str(tail);
+    result
+}
+
+/// Replace balanced `{...}` spans with `placeholder`, tracking nesting depth
+/// and ignoring braces inside string literals. Unbalanced spans are left as-is.
+fn normalize_json(line: &str, placeholder: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+
+        let mut depth = 0usize;
+        let mut in_string = false;
+        let mut escaped = false;
+        let mut end = None;
+
+        for (i, c) in rest[start..].char_indices() {
+            if in_string {
+                match c {
+                    _ if escaped => escaped = false,
+                    '\\' => escaped = true,
+                    '"' => in_string = false,
+                    _ => {}
+                }
+                continue;
+            }
+            match c {
+                '"' => in_string = true,
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(start + i + 1);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        match end {
+            Some(end) => {
+                result.push_str(placeholder);
+                rest = &rest[end..];
+            }
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+
+    result.push_str(rest);
+    result
+}
+
+/// How deeply `--normalize-arrays` follows nested lists; deeper openers start a fresh scan
+const MAX_ARRAY_DEPTH: usize = 32;
+
+/// A list being scanned by `scan_array`: where it opened, the closer it
+/// expects, its element count and the spans of complete lists inside it that
+/// should collapse if it turns out not to
+struct ArrayFrame {
+    start: usize,
+    close: u8,
+    elements: usize,
+    spans: Vec<(usize, usize)>,
+}
+
+fn array_closer(b: u8) -> Option<u8> {
+    match b {
+        b'[' => Some(b']'),
+        b'{' => Some(b'}'),
+        b'(' => Some(b')'),
+        _ => None,
+    }
+}
+
+/// Scan the list opened at `bytes[start]`, returning the spans of lists of two
+/// or more numbers (or of such lists) to collapse and where to resume. Lists
+/// nested inside a broken or single-element list are still collapsed, and no
+/// byte is examined twice across calls, so the caller stays linear
+fn scan_array(bytes: &[u8], start: usize) -> (Vec<(usize, usize)>, usize) {
+    let mut stack = vec![ArrayFrame {
+        start,
+        close: array_closer(bytes[start]).unwrap_or(0),
+        elements: 0,
+        spans: Vec::new(),
+    }];
+    let mut i = start + 1;
+
+    // Anything that isn't a number or a list (words, mismatched or missing
+    // closers, missing separators) breaks every list still open
+    let failed = |stack: Vec<ArrayFrame>, at: usize| {
+        (stack.into_iter().flat_map(|frame| frame.spans).collect(), at)
+    };
+
+    loop {
+        while i < bytes.len() && (bytes[i] == b' ' || bytes[i] == b',') {
+            i += 1;
+        }
+        let Some(&b) = bytes.get(i) else {
+            return failed(stack, i);
+        };
+        let top = stack.last_mut().expect("scan stack is never empty");
+        if b == top.close {
+            if top.elements == 0 {
+                return failed(stack, i);
+            }
+            i += 1;
+            let frame = stack.pop().expect("scan stack is never empty");
+            let spans = if frame.elements >= 2 {
+                vec![(frame.start, i)]
+            } else {
+                frame.spans
+            };
+            match stack.last_mut() {
+                Some(parent) => {
+                    parent.spans.extend(spans);
+                    parent.elements += 1;
+                }
+                None => return (spans, i),
+            }
+        } else if let Some(close) = array_closer(b) {
+            if stack.len() >= MAX_ARRAY_DEPTH {
+                return failed(stack, i);
+            }
+            stack.push(ArrayFrame {
+                start: i,
+                close,
+                elements: 0,
+                spans: Vec::new(),
+            });
+            i += 1;
+            continue;
+        } else if b == b'-' || b.is_ascii_digit() {
+            i += 1;
+            while i < bytes.len() && (bytes[i].is_ascii_digit() || bytes[i] == b'.') {
+                i += 1;
+            }
+            if !bytes[i - 1].is_ascii_digit() {
+                return failed(stack, i);
+            }
+            top.elements += 1;
+        } else {
+            return failed(stack, i);
+        }
+
+        // Elements must be separated
+        let close = stack.last().map_or(0, |frame| frame.close);
+        if !matches!(bytes.get(i), Some(b' ' | b',')) && bytes.get(i) != Some(&close) {
+            return failed(stack, i);
+        }
+    }
+}
+
+/// Replace bracketed lists of two or more numbers with `placeholder`
+fn normalize_arrays(line: &str, placeholder: &str) -> String {
+    let bytes = line.as_bytes();
+    let mut result = String::with_capacity(line.len());
+    let mut last = 0;
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if array_closer(bytes[i]).is_none() {
+            i += 1;
+            continue;
+        }
+        let (spans, next) = scan_array(bytes, i);
+        for (start, end) in spans {
+            result.push_str(&line[last..start]);
+            result.push_str(placeholder);
+            last = end;
+        }
+        i = next;
+    }
+
+    result.push_str(&line[last..]);
+    result
+}
+
+/// Characters that may not border a base64 token (so identifiers like
+/// `AbstractBeanFactory_impl` or mangled symbols aren't split into blobs)
+fn is_base64_neighbor(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '+' | '/' | '_')
+}
+
+/// Base64 blobs have a length that's a multiple of 4 and mix upper and
+/// lower case with digits or `+`/`=`; ordinary words and paths don't
+fn looks_like_base64(token: &str) -> bool {
+    token.len().is_multiple_of(4)
+        && !token.starts_with('/')
+        && token.chars().any(|c| c.is_ascii_uppercase())
+        && token.chars().any(|c| c.is_ascii_lowercase())
+        && token.chars().any(|c| c.is_ascii_digit() || c == '+' || c == '=')
+}
+
+/// Replace standalone base64 tokens with `placeholder`
+fn normalize_base64(line: &str, placeholder: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut last = 0;
+
+    for m in BASE64_PATTERN.find_iter(line) {
+        let before = line[..m.start()].chars().next_back();
+        let after = line[m.end()..].chars().next();
+        let bounded = !before.is_some_and(is_base64_neighbor) && !after.is_some_and(is_base64_neighbor);
+
+        if bounded && looks_like_base64(m.as_str()) {
+            result.push_str(&line[last..m.start()]);
+            result.push_str(placeholder);
+            last = m.end();
+        }
+    }
+
+    result.push_str(&line[last..]);
+    result
+}
+
+/// Split `line` right after its first `n` whitespace-separated fields
+fn split_after_fields(line: &str, n: usize) -> (&str, &str) {
+    if n == 0 {
+        return ("", line);
+    }
+
+    let mut seen = 0;
+    let mut in_field = false;
+    for (i, c) in line.char_indices() {
+        if c.is_whitespace() {
+            if in_field && seen == n {
+                return line.split_at(i);
+            }
+            in_field = false;
+        } else if !in_field {
+            in_field = true;
+            seen += 1;
+        }
+    }
+
+    (line, "")
+}
+
+/// Normalize a line by replacing variable parts with placeholders,
+/// keeping the first `literal_prefix` fields (e.g. a syslog frame) as-is
+fn normalize_line(line: &str, opts: &Options) -> String {
+    let columns = (!opts.column_rules.is_empty()).then(|| apply_column_rules(line, opts));
+    let line = columns.as_deref().unwrap_or(line);
+    let (prefix, rest) = split_after_fields(line, opts.literal_prefix);
+    format!("{}{}", prefix, normalize_text(rest, opts))
+}
+
+/// Replace the fields targeted by `opts.column_rules`, keeping the spacing between fields
+fn apply_column_rules(line: &str, opts: &Options) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut last = 0;
+
+    for (i, field) in FIELD.find_iter(line).enumerate() {
+        let rule = opts.column_rules.iter().find(|rule| {
+            rule.column == i + 1 && rule.pattern.as_ref().is_none_or(|p| p.is_match(field.as_str()))
+        });
+        if let Some(rule) = rule {
+            result.push_str(&line[last..field.start()]);
+            result.push_str(&opts.delimiters.wrap(&rule.placeholder));
+            last = field.end();
+        }
+    }
+
+    result.push_str(&line[last..]);
+    result
+}
+
+/// Whether a token mixing letters and digits reads like a name (`Log4jConfigLoader`,
+/// `HTTP2Server`, `sha256sum`) rather than a random ID (`a1b2c3d4e5`, `Xk7Qp2Lm9`):
+/// names have at most two digit runs and a piece of three or more letters with a
+/// vowel. Hex strings are always random.
+fn looks_like_name(token: &str) -> bool {
+    if token.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return false;
+    }
+    let digit_runs = token
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|run| !run.is_empty())
+        .count();
+    digit_runs <= 2
+        && IDENT_PIECE.find_iter(token).any(|piece| {
+            piece.as_str().len() >= 3
+                && piece.as_str().chars().any(|c| "aeiouAEIOU".contains(c))
+        })
+}
+
+fn normalize_text(line: &str, opts: &Options) -> String {
+    let mut result = line.to_string();
+    let var = |name: &str| {
+        debug_assert!(BUILTIN_PLACEHOLDERS.contains(&name), "{} isn't a listed placeholder", name);
+        opts.delimiters.wrap(name)
+    };
+
+    // Sentence punctuation stuck to a field isn't part of it; fields that are
+    // nothing but punctuation (`...`, `?`) are kept
+    if opts.trim_punct {
+        result = FIELD
+            .replace_all(&result, |caps: &regex::Captures| {
+                let trimmed = caps[0].trim_end_matches([',', ';', '.', '!', '?']);
+                if trimmed.is_empty() {
+                    caps[0].to_string()
+                } else {
+                    trimmed.to_string()
+                }
+            })
+            .to_string();
+    }
+    // Exact replacements are cheapest and most specific, so they go first
+    if !opts.replacements.is_empty() {
+        result = FIELD
+            .replace_all(&result, |caps: &regex::Captures| {
+                opts.replacements
+                    .get(&caps[0])
+                    .cloned()
+                    .unwrap_or_else(|| caps[0].to_string())
+            })
+            .to_string();
+    }
+    // User rules take precedence over the built-in patterns
+    if !opts.rules.is_empty() {
+        result = opts.rules.apply(&result);
+    }
+    // Only the opt-in detectors below run without the built-in patterns
+    if !opts.no_inherent {
+        // Collapse separator runs like ===== or ------ of any length; `...` and
+        // `???` (ellipses, unknown symbols) aren't separators
+        result = FIELD
+            .replace_all(&result, |caps: &regex::Captures| {
+                let field = &caps[0];
+                let first = field.as_bytes()[0];
+                let separator = field.len() >= 3
+                    && b"=-*#_~+".contains(&first)
+                    && field.bytes().all(|b| b == first);
+                if separator {
+                    var("sep")
+                } else {
+                    field.to_string()
+                }
+            })
+            .to_string();
+        // Spell bracketed levels one way, so padding and case don't split templates
+        result = BRACKETED_LEVEL
+            .replace_all(&result, |caps: &regex::Captures| match (&caps[1], &caps[3]) {
+                ("[", "]") | ("(", ")") => {
+                    format!("{}{}{}", &caps[1], caps[2].to_uppercase(), &caps[3])
+                }
+                _ => caps[0].to_string(),
+            })
+            .to_string();
+    }
+    // Collapse embedded JSON payloads before their contents get normalized
+    if opts.normalize_json {
+        result = normalize_json(&result, &var("json"));
+    }
+    // Collapse free-form quoted messages so the structure around them groups
+    if opts.normalize_quoted {
+        result = QUOTED.replace_all(&result, NoExpand(&var("str"))).to_string();
+    }
+    // Collapse `key: value` values wholesale, since they may span several words
+    if opts.kv_colon {
+        let value = var("val");
+        result = KV_COLON
+            .replace_all(&result, |caps: &regex::Captures| {
+                format!("{}{}: {}", &caps[1], &caps[2], value)
+            })
+            .to_string();
+    }
+    // Collapse path tails, keeping the leading segments that name the endpoint
+    if let Some(depth) = opts.path_depth {
+        let tail = var("path-tail");
+        result = URL_PATH
+            .replace_all(&result, |caps: &regex::Captures| {
+                format!("{}{}", &caps[1], truncate_path(&caps[2], depth, &tail))
+            })
+            .to_string();
+    }
+    // Replace custom-format timestamps wholesale; lookalikes chrono rejects stay literal
+    if let Some(date_format) = &opts.date_format {
+        let time = var("time");
+        result = date_format
+            .pattern
+            .replace_all(&result, |caps: &regex::Captures| {
+                if date_format.parses(&caps[0]) {
+                    time.clone()
+                } else {
+                    caps[0].to_string()
+                }
+            })
+            .to_string();
+    }
+    // Collapse numeric arrays before their elements get normalized one by one
+    if opts.normalize_arrays {
+        result = normalize_arrays(&result, &var("array"));
+    }
+    if opts.no_inherent {
+        if opts.epoch {
+            result = EPOCH.replace_all(&result, NoExpand(&var("epoch"))).to_string();
+        }
+        return result;
+    }
+    // Replace Windows paths before their segments get picked apart
+    result = WIN_PATH.replace_all(&result, NoExpand(&var("winpath"))).to_string();
+    // Replace monetary amounts before their digits get picked apart
+    result = MONEY_PATTERN.replace_all(&result, NoExpand(&var("money"))).to_string();
+    // Replace coordinates while both halves still have their decimals; a pair
+    // inside a longer list (load averages, CSV fields) is data, not a position
+    result = COORD_PATTERN
+        .replace_all(&result, |caps: &regex::Captures| {
+            let (start, end) = caps.get(0).map_or((0, 0), |m| (m.start(), m.end()));
+            let in_list =
+                LIST_BEFORE.is_match(&result[..start]) || LIST_AFTER.is_match(&result[end..]);
+            let lat: f64 = caps[1].parse().unwrap_or(f64::MAX);
+            let lon: f64 = caps[2].parse().unwrap_or(f64::MAX);
+            if !in_list && lat <= 90.0 && lon.abs() <= 180.0 {
+                var("coord")
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string();
+    // Replace phone numbers; a four-digit last group keeps dotted IPs out,
+    // and going before versions keeps 555.123.4567 from passing for one
+    result = PHONE_PATTERN
+        .replace_all(&result, |caps: &regex::Captures| {
+            format!("{}{}", caps.get(1).map_or("", |m| m.as_str()), var("phone"))
+        })
+        .to_string();
+    // Replace semantic versions, leaving four-part runs like IPv4 addresses alone
+    result = SEMVER_PATTERN
+        .replace_all(&result, |caps: &regex::Captures| {
+            let core = caps[0].split(['-', '+']).next().unwrap_or_default();
+            if core.split('.').count() == 3 {
+                var("version")
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string();
+    // Replace container IDs before they pass for digests, keeping the scheme or label
+    result = CONTAINER_ID
+        .replace_all(&result, |caps: &regex::Captures| format!("{}{}", &caps[1], var("container")))
+        .to_string();
+    // Replace the random suffixes of pod names, keeping the deployment name
+    result = POD_NAME
+        .replace_all(&result, |caps: &regex::Captures| format!("{}-{}", &caps[1], var("pod")))
+        .to_string();
+    // Replace hex digests by their length
+    result = HASH_PATTERN
+        .replace_all(&result, |caps: &regex::Captures| match caps[0].len() {
+            32 => var("md5"),
+            40 => var("sha1"),
+            64 => var("sha256"),
+            _ => caps[0].to_string(),
+        })
+        .to_string();
+    // Replace base64 blobs before their digits get picked up as numbers
+    result = normalize_base64(&result, &var("base64"));
+    // Replace bracketed hex addresses first (more specific)
+    result = BRACKETED_HEX.replace_all(&result, NoExpand(&var("addr"))).to_string();
+    // Other wrappers keep their delimiters around the address
+    result = WRAPPED_HEX
+        .replace_all(&result, |caps: &regex::Captures| match caps.get(1) {
+            Some(_) => format!("({})", var("hex")),
+            None => format!("{{{}}}", var("hex")),
+        })
+        .to_string();
+    // Replace hex addresses
+    result = HEX_ADDR.replace_all(&result, NoExpand(&var("hex"))).to_string();
+    // Replace brace-wrapped GUIDs, keeping the braces
+    result = BRACE_GUID
+        .replace_all(&result, NoExpand(&format!("{{{}}}", var("uuid"))))
+        .to_string();
+    // Replace UUIDs
+    result = UUID_PATTERN.replace_all(&result, NoExpand(&var("uuid"))).to_string();
+    // Replace plain UUIDs before their groups pass for random IDs
+    result = ANY_UUID.replace_all(&result, NoExpand(&var("uuid"))).to_string();
+    // Replace process IDs, keeping the daemon name
+    result = PROCESS_ID
+        .replace_all(&result, |caps: &regex::Captures| {
+            if caps[2].len() >= 2 || !caps[3].is_empty() {
+                format!("{}[{}]{}", &caps[1], var("pid"), &caps[3])
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string();
+    // Replace thread IDs
+    result = THREAD_ID
+        .replace_all(&result, NoExpand(&format!("Thread_{}", var("id"))))
+        .to_string();
+    // Replace labeled thread IDs, keeping the label
+    result = LABELED_THREAD
+        .replace_all(&result, |caps: &regex::Captures| match caps.get(1) {
+            Some(label) => format!("{}{}]", label.as_str(), var("tid")),
+            None => format!("{}{}", &caps[2], var("tid")),
+        })
+        .to_string();
+    // Replace timestamps
+    result = TIMESTAMP.replace_all(&result, NoExpand(&var("time"))).to_string();
+    // Replace durations before their digits get picked up as numbers
+    result = DURATION_PATTERN.replace_all(&result, NoExpand(&var("duration"))).to_string();
+    // Replace epoch timestamps; this is purely length-based, so it's opt-in
+    if opts.epoch {
+        result = EPOCH.replace_all(&result, NoExpand(&var("epoch"))).to_string();
+    }
+    // Replace ordinals
+    result = ORDINAL.replace_all(&result, NoExpand(&var("ordinal"))).to_string();
+    // Replace comma-grouped numbers, judging their size without the commas
+    result = GROUPED_NUM
+        .replace_all(&result, |caps: &regex::Captures| {
+            let digits = caps[0].bytes().filter(u8::is_ascii_digit).count();
+            if digits >= 5 || opts.all_numbers_variable {
+                num_placeholder(digits, opts)
+            } else {
+                caps[0].to_string()
+            }
+        })
+        .to_string();
+    // Replace large numbers (but keep small ones like line offsets, unless
+    // asked not to), absorbing a leading sign so deltas group with their
+    // unsigned forms
+    let (signed_num, plain_num) = if opts.all_numbers_variable {
+        (&*SIGNED_ANY_NUM, &*ANY_NUM)
+    } else {
+        (&*SIGNED_NUM, &*LARGE_NUM)
+    };
+    result = signed_num
+        .replace_all(&result, |caps: &regex::Captures| {
+            format!("{}{}", &caps[1], num_placeholder(caps[2].len(), opts))
+        })
+        .to_string();
+    result = plain_num
+        .replace_all(&result, |caps: &regex::Captures| {
+            let start = caps.get(0).map_or(0, |m| m.start());
+            if EVENT_ID.is_match(&result[..start]) {
+                caps[0].to_string()
+            } else {
+                num_placeholder(caps[0].len(), opts)
+            }
+        })
+        .to_string();
+    // Replace random alphanumeric IDs last, so durations, ordinals and the like
+    // already hold their own placeholders; requiring both letters and digits
+    // keeps words and numbers out, and identifiers like `Windows10` stay literal
+    result = MIXED_ID
+        .replace_all(&result, |caps: &regex::Captures| {
+            let token = &caps[0];
+            let letters = token.bytes().any(|b| b.is_ascii_alphabetic());
+            let digits = token.bytes().any(|b| b.is_ascii_digit());
+            if letters && digits && !looks_like_name(token) {
+                var("id")
+            } else {
+                token.to_string()
+            }
+        })
+        .to_string();
+
+    result
+}
+
+/// Placeholder for a number with `digits` digits
+fn num_placeholder(digits: usize, opts: &Options) -> String {
+    if opts.num_width {
+        opts.delimiters.wrap(&format!("num:{}", digits))
+    } else {
+        opts.delimiters.wrap("num")
+    }
+}
+
+/// Normalize indentation - strip it entirely for better grouping
+fn normalize_indent(line: &str) -> String {
+    INDENT_PATTERN.replace(line, "").to_string()
+}
+
+/// Grouping key for a template: whitespace runs (including tabs around empty
+/// fields) collapse to one space, so spacing alone never splits a group
+fn spacing_key(template: &str) -> String {
+    WHITESPACE_RUN.replace_all(template.trim_end(), " ").to_string()
+}
+
+/// Re-annotate the `<num>` placeholders of a template with their digit
+/// widths, leaving placeholders whose width varied as plain `<num>`
+fn render_num_widths(template: &str, widths: &[Option<usize>], delims: &Delimiters) -> String {
+    let plain = delims.wrap("num");
+    let mut parts = template.split(plain.as_str());
+    let mut result = parts.next().unwrap_or_default().to_string();
+
+    for (i, part) in parts.enumerate() {
+        match widths.get(i).copied().flatten() {
+            Some(width) => result.push_str(&delims.wrap(&format!("num:{}", width))),
+            None => result.push_str(&plain),
+        }
+        result.push_str(part);
+    }
+
+    result
+}
+
+/// Stable identifier for a template: 32-bit FNV-1a of its text, as hex
+pub fn template_id(template: &str) -> String {
+    let mut hash: u32 = 0x811c_9dc5;
+    for byte in template.bytes() {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    format!("{:08x}", hash)
+}
+
+/// Escape a value for the `kv` format: `\` becomes `\\`, and tab, newline and
+/// carriage return become `\t`, `\n` and `\r`, so fields never contain a raw tab
+pub fn escape_kv(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => result.push_str("\\\\"),
+            '\t' => result.push_str("\\t"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Expand the `\n`, `\t` and `\\` escape sequences of `line`; others are kept as written
+fn unescape(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            Some('\\') => result.push('\\'),
+            Some(other) => {
+                result.push('\\');
+                result.push(other);
+            }
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// A markdown table cell showing `value` as code: pipes are escaped (GFM
+/// requires it even inside code spans) and values holding a backtick get a
+/// double-backtick span
+pub fn markdown_cell(value: &str) -> String {
+    let escaped = value.replace('|', "\\|");
+    if escaped.contains('`') {
+        format!("`` {} ``", escaped)
+    } else {
+        format!("`{}`", escaped)
+    }
+}
+
+/// Cut `line` to `max` characters, appending a `marker` token when anything was dropped
+fn truncate_line(line: &str, max: usize, marker: &str) -> Option<String> {
+    let (end, _) = line.char_indices().nth(max)?;
+    Some(format!("{} {}", &line[..end], marker))
+}
+
+/// How many line numbers `--line-numbers` keeps per template
+pub const MAX_LINE_NUMBERS: usize = 20;
+
+/// Render sorted line numbers compactly, collapsing contiguous runs: `1-3, 7`
+fn format_ranges(numbers: &[usize]) -> String {
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < numbers.len() {
+        let start = numbers[i];
+        let mut end = start;
+        while i + 1 < numbers.len() && numbers[i + 1] == end + 1 {
+            i += 1;
+            end = numbers[i];
+        }
+        if start == end {
+            ranges.push(start.to_string());
+        } else {
+            ranges.push(format!("{}-{}", start, end));
+        }
+        i += 1;
+    }
+    ranges.join(", ")
+}
+
+/// Width in characters of the longest `--bars` bar
+const BAR_WIDTH: usize = 20;
+
+/// A bar of `count / max * BAR_WIDTH` characters, drawn in eighths of a block
+fn render_bar(count: usize, max: usize) -> String {
+    const PARTIAL: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+    // Every group gets at least a sliver
+    let eighths = (count * BAR_WIDTH * 8 / max).max(1);
+    let mut bar = "█".repeat(eighths / 8);
+    let remainder = eighths % 8;
+    if remainder > 0 {
+        bar.push(PARTIAL[remainder - 1]);
+    }
+    bar
+}
+
+/// Group and deduplicate lines
+struct LineGroup {
+    normalized: String,
+    count: usize,
+    /// Digit width of each `<num>` placeholder, `None` once widths disagree
+    num_widths: Vec<Option<usize>>,
+    /// First original line that produced this group
+    first_line: String,
+    /// Input position of `first_line`, used to keep outliers in input order
+    first_seen: usize,
+    /// How often each leading indentation was seen (with `preserve_spacing`)
+    indents: HashMap<String, usize>,
+    /// Source line numbers, capped at `MAX_LINE_NUMBERS` (with `line_numbers`)
+    line_numbers: Vec<usize>,
+}
+
+/// Names of the placeholders the built-in patterns emit, besides `num`; `normalize_text`
+/// asserts in debug builds that every name it emits is listed
+const BUILTIN_PLACEHOLDERS: &[&str] = &[
+    "addr", "array", "base64", "blank", "container", "coord", "duration", "epoch", "hex", "id",
+    "json", "md5", "money", "ordinal", "path-tail", "phone", "pid", "pod", "redacted", "sep",
+    "sha1", "sha256", "str", "tid", "time", "truncated", "uuid", "val", "version", "winpath",
+];
+
+/// Incremental compactor that accepts lines one at a time
+///
+/// Each line is normalized and grouped as it arrives, so `snapshot` only
+/// re-sorts and renders the groups accumulated so far. Pushing every line
+/// of an input and then taking a snapshot gives the same output as `process`.
+pub struct Compactor<'a> {
+    opts: &'a Options,
+    /// Matches width-annotated number placeholders like `<num:5>`
+    num_width: Regex,
+    /// Matches the built-in placeholders like `<hex>` or `<num:5>`, but not
+    /// literal text that happens to be wrapped alike (a Java `<init>`)
+    placeholder: Regex,
+    total_lines: usize,
+    /// Source lines pushed; fewer than `total_lines` when `unescape` splits lines
+    physical_lines: usize,
+    /// Input lines skipped before `from`, so line numbers match the source
+    skipped_lines: usize,
+    groups: HashMap<String, LineGroup>,
+    system_images: usize,
+    app_images: Vec<String>,
+    /// Lines skipped under `json_field` for not being JSON or lacking the field
+    skipped_json: usize,
+}
+
+impl<'a> Compactor<'a> {
+    pub fn new(opts: &'a Options) -> Self {
+        let open = regex::escape(&opts.delimiters.open);
+        let close = regex::escape(&opts.delimiters.close);
+        let num_width = format!(r"{}num:(\d+){}", open, close);
+        // Built-in names, plus those of user placeholders written in the delimiters
+        let user = |text: &'a str| {
+            let name = text.strip_prefix(opts.delimiters.open.as_str())?;
+            name.strip_suffix(opts.delimiters.close.as_str()).filter(|name| !name.is_empty())
+        };
+        let mut names: Vec<String> = vec![r"num(?::\d+)?".to_string()];
+        names.extend(BUILTIN_PLACEHOLDERS.iter().map(|name| regex::escape(name)));
+        names.extend(opts.column_rules.iter().map(|rule| regex::escape(&rule.placeholder)));
+        names.extend(opts.replacements.values().filter_map(|p| user(p)).map(regex::escape));
+        names.extend(opts.rules.placeholders().filter_map(user).map(regex::escape));
+        let placeholder = format!(r"{}(?:{}){}", open, names.join("|"), close);
+        Compactor {
+            opts,
+            num_width: Regex::new(&num_width).unwrap(),
+            placeholder: Regex::new(&placeholder).unwrap(),
+            total_lines: 0,
+            physical_lines: 0,
+            skipped_lines: 0,
+            groups: HashMap::new(),
+            system_images: 0,
+            app_images: Vec::new(),
+            skipped_json: 0,
+        }
+    }
+
+    /// Add a single line of input (without its line terminator)
+    ///
+    /// With `unescape`, a line holding escaped newlines counts as several lines,
+    /// all reported under its own line number; so does a `json_field` value
+    /// holding newlines.
+    pub fn push_line(&mut self, line: &str) {
+        let opts = self.opts;
+        self.physical_lines += 1;
+
+        // Structured input: only the chosen field is templated (and unescaped)
+        let message;
+        let line = match &opts.json_field {
+            Some(field) => {
+                let value = serde_json::from_str::<serde_json::Value>(line).ok();
+                match value.as_ref().and_then(|v| v.get(field)).and_then(|v| v.as_str()) {
+                    Some(text) => {
+                        message = text.to_string();
+                        message.as_str()
+                    }
+                    None => {
+                        self.total_lines += 1;
+                        self.skipped_json += 1;
+                        return;
+                    }
+                }
+            }
+            None => line,
+        };
+
+        let unescaped;
+        let line = if opts.unescape && line.contains('\\') {
+            unescaped = unescape(line);
+            unescaped.as_str()
+        } else {
+            line
+        };
+        // Unescaped text and decoded JSON strings may hold newlines; each part is a line
+        for part in line.split('\n') {
+            self.push_logical_line(part);
+        }
+    }
+
+    fn push_logical_line(&mut self, line: &str) {
+        let opts = self.opts;
+        // `lines()` leaves a carriage return on a final line with no `\n`
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        self.total_lines += 1;
+
+        // Color codes would otherwise stick to the fields they wrap
+        let stripped = if opts.strip_ansi {
+            ANSI_ESCAPE.replace_all(line, "")
+        } else {
+            line.into()
+        };
+        let line = stripped.as_ref();
+
+        // Guard against pathological lines before doing any work on them
+        let truncated = opts
+            .max_line_length
+            .and_then(|max| truncate_line(line, max, &opts.delimiters.wrap("truncated")));
+        let line = truncated.as_deref().unwrap_or(line);
+
+        // Redact before the line is stored anywhere, so samples can't leak it
+        let redacted = (!opts.redact.is_empty()).then(|| redact_line(line, opts));
+        let line = redacted.as_deref().unwrap_or(line);
+
+        // Exact deduplication bypasses normalization entirely
+        if opts.dedup {
+            self.add_to_group(line.to_string(), line, Vec::new());
+            return;
+        }
+
+        // Blank and whitespace-only lines get their own explicit group
+        if line.trim().is_empty() {
+            self.add_to_group(opts.delimiters.wrap("blank"), line, Vec::new());
+            return;
+        }
+
+        // Step 1: Separate binary images from other content
+        if BINARY_IMAGE.is_match(line) {
+            if SYSTEM_LIB.is_match(line) {
+                self.system_images += 1;
+            } else {
+                // Keep app/plugin images (they're relevant for debugging)
+                self.app_images.push(normalize_line(line, opts));
+            }
+            return;
+        }
+
+        // Step 2: Normalize and group regular lines
+        // First normalize variable parts (hex, etc.)
+        let mut normalized = normalize_line(line, opts);
+        // Pull number widths out of the key so differing widths still group
+        let mut widths = Vec::new();
+        if opts.num_width {
+            widths = self
+                .num_width
+                .captures_iter(&normalized)
+                .map(|caps| caps[1].parse().unwrap_or_default())
+                .collect();
+            normalized = self
+                .num_width
+                .replace_all(&normalized, NoExpand(&opts.delimiters.wrap("num")))
+                .to_string();
+        }
+        // Then normalize indentation
+        let key = normalize_indent(&normalized);
+        let indent = normalized[..normalized.len() - key.len()].to_string();
+
+        let group = self.add_to_group(key, line, widths);
+        if opts.preserve_spacing {
+            *group.indents.entry(indent).or_default() += 1;
+        }
+    }
+
+    /// Count `line` towards the group for `key`; its first line's spacing is the one shown
+    fn add_to_group(&mut self, key: String, line: &str, widths: Vec<usize>) -> &mut LineGroup {
+        let first_seen = self.total_lines;
+        let line_number = self.skipped_lines + self.physical_lines;
+        let track_lines = self.opts.line_numbers;
+        let group_key = if self.opts.dedup {
+            key.clone()
+        } else {
+            spacing_key(&key)
+        };
+
+        self.groups
+            .entry(group_key)
+            .and_modify(|g| {
+                g.count += 1;
+                // Fragments of one unescaped line share its number
+                let repeated = g.line_numbers.last() == Some(&line_number);
+                if track_lines && !repeated && g.line_numbers.len() < MAX_LINE_NUMBERS {
+                    g.line_numbers.push(line_number);
+                }
+                for (seen, width) in g.num_widths.iter_mut().zip(&widths) {
+                    if *seen != Some(*width) {
+                        *seen = None;
+                    }
+                }
+            })
+            .or_insert_with(|| LineGroup {
+                normalized: key,
+                count: 1,
+                num_widths: widths.iter().copied().map(Some).collect(),
+                first_line: line.to_string(),
+                first_seen,
+                indents: HashMap::new(),
+                line_numbers: if track_lines {
+                    vec![line_number]
+                } else {
+                    Vec::new()
+                },
+            })
+    }
+
+    /// Account for an input line that is not pushed, keeping later line numbers aligned
+    fn skip_line(&mut self) {
+        self.skipped_lines += 1;
+    }
+
+    /// Final template text of a group
+    fn template(&self, group: &LineGroup) -> String {
+        let mut template = if self.opts.num_width {
+            render_num_widths(&group.normalized, &group.num_widths, &self.opts.delimiters)
+        } else {
+            group.normalized.clone()
+        };
+        if self.opts.annotate_types {
+            let Delimiters { open, close } = &self.opts.delimiters;
+            let mut position = 0;
+            template = self
+                .placeholder
+                .replace_all(&template, |caps: &regex::Captures| {
+                    let name = &caps[0][open.len()..caps[0].len() - close.len()];
+                    let annotated = format!("{}{}:{}{}", open, position, name, close);
+                    position += 1;
+                    annotated
+                })
+                .to_string();
+        }
+
+        // Restore the dominant indentation, preferring the shorter one on ties
+        let indent = group
+            .indents
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(indent, _)| indent.as_str())
+            .unwrap_or_default();
+        format!("{}{}", indent, template)
+    }
+
+    /// How many pages of `page_size` templates `snapshot` has to show, if paging
+    pub fn page_count(&self) -> Option<usize> {
+        let size = self.opts.page_size.filter(|&size| size > 0)?;
+        let listed = match &self.opts.matching {
+            Some(wanted) => self.groups.values().filter(|g| self.template(g) == *wanted).count(),
+            None => self.groups.len(),
+        };
+        Some(listed.div_ceil(size))
+    }
+
+    /// Each group's final template and count, in no particular order
+    pub fn templates(&self) -> HashMap<String, usize> {
+        self.groups
+            .values()
+            .map(|group| (self.template(group), group.count))
+            .collect()
+    }
+
+    /// Render the groups accumulated so far
+    pub fn snapshot(&self) -> String {
+        let opts = self.opts;
+
+        // Health-check mode: just the template count, even for empty input
+        if opts.count_only {
+            return self.groups.len().to_string();
+        }
+
+        if self.total_lines == 0 {
+            return String::new();
+        }
+
+        // Outlier mode: original lines of single-occurrence groups, in input order
+        if opts.invert {
+            let mut outliers: Vec<&LineGroup> =
+                self.groups.values().filter(|g| g.count == 1).collect();
+            outliers.sort_by_key(|g| g.first_seen);
+            return outliers
+                .iter()
+                .map(|g| g.first_line.as_str())
+                .collect::<Vec<_>>()
+                .join("\n");
+        }
+
+        // Step 3: Sort by count (descending, ties alphabetical) or by template
+        let mut sorted_groups: Vec<&LineGroup> = self.groups.values().collect();
+        match opts.sort {
+            SortKey::Count => sorted_groups.sort_by(|a, b| {
+                b.count.cmp(&a.count).then_with(|| a.normalized.cmp(&b.normalized))
+            }),
+            SortKey::Template => sorted_groups.sort_by(|a, b| a.normalized.cmp(&b.normalized)),
+        }
+        if opts.reverse {
+            sorted_groups.reverse();
+        }
+        // The summary counts every template, not just the ones listed
+        let template_count = sorted_groups.len();
+        if let Some(wanted) = &opts.matching {
+            sorted_groups.retain(|g| self.template(g) == *wanted);
+            if sorted_groups.is_empty() {
+                return String::new();
+            }
+        }
+        // Keep one page of groups, remembering how many pages there are
+        let mut pages = None;
+        if let Some(size) = opts.page_size.filter(|&size| size > 0) {
+            let page = opts.page.max(1);
+            pages = Some((page, sorted_groups.len().div_ceil(size)));
+            sorted_groups = sorted_groups.into_iter().skip((page - 1) * size).take(size).collect();
+        }
+
+        // Step 4: Format output
+        let mut output = Vec::new();
+
+        // Key-value output carries only the groups, one per line
+        if opts.format == Format::Kv {
+            for group in sorted_groups {
+                let template = self.template(group);
+                let mut line = format!("count={}", group.count);
+                if opts.ids {
+                    line.push_str(&format!("\tid={}", template_id(&template)));
+                }
+                line.push_str(&format!("\ttemplate={}", escape_kv(&template)));
+                output.push(line);
+            }
+            return output.join("\n");
+        }
+
+        // Markdown output is a single table, ready to paste into a report
+        if opts.format == Format::Markdown {
+            if opts.ids {
+                output.push("| Count | Id | Template | Sample |".to_string());
+                output.push("|------:|----|----------|--------|".to_string());
+            } else {
+                output.push("| Count | Template | Sample |".to_string());
+                output.push("|------:|----------|--------|".to_string());
+            }
+            for group in sorted_groups {
+                let template = self.template(group);
+                let mut line = format!("| {} |", group.count);
+                if opts.ids {
+                    line.push_str(&format!(" {} |", template_id(&template)));
+                }
+                line.push_str(&format!(
+                    " {} | {} |",
+                    markdown_cell(&template),
+                    markdown_cell(&group.first_line)
+                ));
+                output.push(line);
+            }
+            return output.join("\n");
+        }
+
+        if opts.summary {
+            output.push(format!(
+                "# {} lines -> {} templates",
+                self.total_lines, template_count
+            ));
+        }
+
+        let max_count = sorted_groups.iter().map(|g| g.count).max().unwrap_or(1);
+        for group in sorted_groups {
+            let template = self.template(group);
+
+            let mut line = String::new();
+            if opts.ids {
+                line.push_str(&format!("[id={}] ", template_id(&template)));
+            }
+            if group.count > 1 {
+                line.push_str(&format!("[{}x] ", group.count));
+            }
+            line.push_str(&template);
+            if opts.bars {
+                line.push(' ');
+                line.push_str(&render_bar(group.count, max_count));
+            }
+            output.push(line);
+
+            if opts.example {
+                output.push(format!("    {}", group.first_line));
+            }
+            if opts.line_numbers {
+                let mut numbers = format_ranges(&group.line_numbers);
+                if group.count > group.line_numbers.len() {
+                    numbers.push_str(", ...");
+                }
+                output.push(format!("    lines: {}", numbers));
+            }
+        }
+
+        if let Some(field) = &opts.json_field
+            && self.skipped_json > 0
+        {
+            output.push(format!(
+                "[{} lines without a string {} field skipped]",
+                self.skipped_json, field
+            ));
+        }
+
+        if let Some((page, total)) = pages {
+            output.push(format!("page {}/{}", page, total));
+        }
+
+        // Step 5: Add binary images summary
+        if self.system_images > 0 || !self.app_images.is_empty() {
+            output.push(String::new());
+            output.push("=== Binary Images ===".to_string());
+
+            output.extend(self.app_images.iter().cloned());
+
+            // Summarize system images
+            if self.system_images > 0 {
+                output.push(format!("[{} system libraries omitted]", self.system_images));
+            }
+        }
+
+        // Step 6: Add compaction metrics
+        if opts.metrics {
+            let templates = self.groups.len();
+            let variables: usize = self
+                .groups
+                .values()
+                .map(|g| self.placeholder.find_iter(&g.normalized).count())
+                .sum();
+            let per_template = |n: usize| n as f64 / templates.max(1) as f64;
+
+            output.push(String::new());
+            output.push("=== Metrics ===".to_string());
+            output.push(format!("reduction ratio: {:.2}", per_template(self.total_lines)));
+            output.push(format!("templates: {}", templates));
+            output.push(format!("avg variables per template: {:.2}", per_template(variables)));
+        }
+
+        output.join("\n")
+    }
+}
+
+/// Read all of `reader` as text, gunzipping it first when `gzip` is set
+///
+/// Bytes are transcoded from `encoding` when given; otherwise they are taken
+/// as UTF-8, with invalid sequences replaced by U+FFFD instead of failing.
+pub fn read_input<R: Read>(
+    mut reader: R,
+    gzip: bool,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<String> {
+    let mut bytes = Vec::new();
+    if gzip {
+        MultiGzDecoder::new(reader).read_to_end(&mut bytes)?;
+    } else {
+        reader.read_to_end(&mut bytes)?;
+    }
+    Ok(match encoding {
+        Some(encoding) => encoding.decode_with_bom_removal(&bytes).0.into_owned(),
+        None => String::from_utf8_lossy(&bytes).into_owned(),
+    })
+}
+
+/// Regular files directly inside `dir`, sorted by name
+pub fn dir_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Files matching a glob pattern like `logs/*.log`, sorted by name
+pub fn glob_files(pattern: &str) -> io::Result<Vec<PathBuf>> {
+    let paths = glob::glob(pattern).map_err(|e| invalid_input(format!("invalid glob: {}", e)))?;
+    let mut files = Vec::new();
+    for path in paths {
+        let path = path.map_err(io::Error::other)?;
+        if path.is_file() {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Concatenate the contents of `files`, gunzipping `.gz` files (or all of
+/// them when `gzip` is set) and making sure each file ends with a newline
+pub fn read_files(
+    files: &[PathBuf],
+    gzip: bool,
+    encoding: Option<&'static Encoding>,
+) -> io::Result<String> {
+    let mut input = String::new();
+    for path in files {
+        let is_gz = gzip || path.extension().is_some_and(|ext| ext == "gz");
+        let with_path =
+            |err: io::Error| io::Error::new(err.kind(), format!("{}: {}", path.display(), err));
+        let file = File::open(path).map_err(with_path)?;
+        input.push_str(&read_input(file, is_gz, encoding).map_err(with_path)?);
+        if !input.is_empty() && !input.ends_with('\n') {
+            input.push('\n');
+        }
+    }
+    Ok(input)
+}
+
+/// Reads the lines appended to a file since the last call, like `tail -f`
+///
+/// A file that shrank (truncated in place) is read again from the start. A
+/// file replaced on rotation (a different inode on Unix) is read to its end,
+/// then the new one is followed from its start. A trailing line without its
+/// `\n` yet is held back until the rest of it arrives.
+pub struct FileFollower {
+    path: PathBuf,
+    file: File,
+    position: u64,
+    pending: Vec<u8>,
+}
+
+impl FileFollower {
+    /// Follow `path` from its beginning
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(FileFollower {
+            path: path.to_path_buf(),
+            file: File::open(path)?,
+            position: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Complete lines written since the previous call
+    pub fn read_lines(&mut self) -> io::Result<Vec<String>> {
+        match fs::metadata(&self.path) {
+            Ok(on_disk) if !same_file(&on_disk, &self.file.metadata()?) => {
+                // Finish the rotated file; its unterminated last line is complete now
+                self.read_appended()?;
+                if self.pending.last().is_some_and(|&b| b != b'\n') {
+                    self.pending.push(b'\n');
+                }
+                self.file = File::open(&self.path)?;
+                self.position = 0;
+            }
+            Ok(on_disk) if on_disk.len() < self.position => {
+                self.file = File::open(&self.path)?;
+                self.position = 0;
+                self.pending.clear();
+            }
+            // Between a rotation and the new file's creation only the old one is left
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+            Ok(_) => {}
+        }
+        self.read_appended()?;
+
+        let Some(end) = self.pending.iter().rposition(|&b| b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.pending.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Append what was written past `position` to `pending`
+    fn read_appended(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(self.position))?;
+        let read = self.file.read_to_end(&mut self.pending)?;
+        self.position += read as u64;
+        Ok(())
+    }
+}
+
+/// Whether two metadata describe the same file, so a rotated-in replacement is noticed
+#[cfg(unix)]
+fn same_file(a: &fs::Metadata, b: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Without inode numbers a replacement is only noticed once it's shorter than the old file
+#[cfg(not(unix))]
+fn same_file(_: &fs::Metadata, _: &fs::Metadata) -> bool {
+    true
+}
+
+/// Compactor fed with the `from`..=`to` line range of `input`, ready for `snapshot`
+pub fn compactor_for<'a>(input: &str, opts: &'a Options) -> Compactor<'a> {
+    let first = opts.from.unwrap_or(1);
+    let last = opts.to.unwrap_or(usize::MAX);
+
+    let mut compactor = Compactor::new(opts);
+    for (i, line) in input.lines().enumerate() {
+        let number = i + 1;
+        if number > last {
+            break;
+        }
+        if number >= first {
+            compactor.push_line(line);
+        } else {
+            compactor.skip_line();
+        }
+    }
+    compactor
+}
+
+/// Compact `input` into its templates; the library entry point
+pub fn compact(input: &str, opts: &Options) -> String {
+    compactor_for(input, opts).snapshot()
+}
+
+/// Templates that appeared, disappeared or changed count between two inputs
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct TemplateDiff {
+    /// Templates only in the candidate, with their counts
+    pub added: Vec<(String, usize)>,
+    /// Templates only in the baseline, with their counts
+    pub removed: Vec<(String, usize)>,
+    /// Templates in both whose counts differ, as (template, baseline, candidate)
+    pub changed: Vec<(String, usize, usize)>,
+}
+
+impl TemplateDiff {
+    /// One `+ [Nx] template`, `- [Nx] template` or `~ [Nx -> Mx] template` line per difference
+    pub fn render(&self) -> String {
+        let mut output = Vec::new();
+        for (template, count) in &self.added {
+            output.push(format!("+ [{}x] {}", count, template));
+        }
+        for (template, count) in &self.removed {
+            output.push(format!("- [{}x] {}", count, template));
+        }
+        for (template, before, after) in &self.changed {
+            output.push(format!("~ [{}x -> {}x] {}", before, after, template));
+        }
+        output.join("\n")
+    }
+}
+
+/// Compare the templates of `baseline` and `candidate`, matching them by template text
+pub fn diff(baseline: &str, candidate: &str, opts: &Options) -> TemplateDiff {
+    let before = compactor_for(baseline, opts).templates();
+    let after = compactor_for(candidate, opts).templates();
+    let mut diff = TemplateDiff::default();
+
+    for (template, &count) in &after {
+        match before.get(template) {
+            None => diff.added.push((template.clone(), count)),
+            Some(&old) if old != count => diff.changed.push((template.clone(), old, count)),
+            Some(_) => {}
+        }
+    }
+    for (template, &count) in &before {
+        if !after.contains_key(template) {
+            diff.removed.push((template.clone(), count));
+        }
+    }
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    diff
+}
+
+/// Compact `input` with `opts`; the old name of `compact`
+#[deprecated(note = "use `compact`")]
+pub fn process(input: &str, opts: &Options) -> String {
+    compact(input, opts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hex_normalization() {
+        let input = "+   1744 ???  (in Live)  load address 0x104fc4000 + 0x115bc98  [0x10611fc98]";
+        let normalized = normalize_line(input, &Options::default());
+        assert_eq!(normalized, "+   1744 ???  (in Live)  load address <hex> + <hex>  <addr>");
+    }
+
+    #[test]
+    fn test_indent_normalization() {
+        let input = "+   1744 ???  (in Live)  load address <hex> + <hex>  <addr>";
+        let result = normalize_indent(input);
+        assert_eq!(result, "1744 ???  (in Live)  load address <hex> + <hex>  <addr>");
+    }
+
+    #[test]
+    fn test_thread_id_normalization() {
+        let input = "1744 Thread_4243153   DispatchQueue_1: com.apple.main-thread";
+        let normalized = normalize_line(input, &Options::default());
+        assert_eq!(normalized, "1744 Thread_<id>   DispatchQueue_1: com.apple.main-thread");
+    }
+
+    #[test]
+    fn test_uuid_normalization() {
+        let input = "<4B0BCBB4-2271-376E-B5C3-CC18D418FC11> /System/Library/foo";
+        let normalized = normalize_line(input, &Options::default());
+        assert_eq!(normalized, "<uuid> /System/Library/foo");
+    }
+
+    #[test]
+    fn test_stack_trace_dedup() {
+        let input = r#"+   1744 ???  (in Live)  load address 0x104fc4000 + 0x114df74  [0x106111f74]
++   1744 ???  (in Live)  load address 0x104fc4000 + 0x115c9c0  [0x1061209c0]
++   1744 ???  (in Live)  load address 0x104fc4000 + 0x1e99770  [0x106e5d770]"#;
+
+        let output = compact(input, &Options::default());
+        // All three lines should be deduped into one with count 3
+        assert!(output.contains("[3x]"));
+        assert!(output.contains("(in Live)"));
+    }
+
+    #[test]
+    fn test_sshd_logs() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 57138 ssh2"#;
+
+        let output = compact(input, &Options::default());
+        // All three lines should be deduped (port numbers normalized as large nums)
+        assert!(output.contains("[3x]"));
+        assert!(output.contains("Failed password"));
+    }
+
+    #[test]
+    fn test_json_normalization() {
+        let input = r#"Dec 10 07:28:03 api: request payload={"id":123,"tags":{"a":"}"}} done
+Dec 10 07:28:04 api: request payload={"id":456,"tags":{}} done
+Dec 10 07:28:05 api: request payload={"id":789} done"#;
+
+        let opts = Options {
+            normalize_json: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        assert_eq!(output, "[3x] Dec 10 <time> api: request payload=<json> done");
+
+        // Unbalanced braces are left alone
+        assert_eq!(normalize_json("open { never closed", "<json>"), "open { never closed");
+    }
+
+    #[test]
+    fn test_summary_header() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:08 LabSZ sshd[24245]: Connection closed by 112.95.230.3 [preauth]"#;
+
+        let opts = Options {
+            summary: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        assert_eq!(output.lines().next(), Some("# 3 lines -> 2 templates"));
+    }
+
+    #[test]
+    fn test_windows_path_normalization() {
+        let input = r#"agent: wrote C:\Users\alice\AppData\Local\Temp\x.tmp
+agent: wrote D:\build\out\app.exe
+agent: wrote \\fileserver\share\reports\q3.xlsx"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] agent: wrote <winpath>");
+
+        // Escape-like sequences in plain text are not paths
+        let plain = normalize_line(r"message\nwith escape", &Options::default());
+        assert_eq!(plain, r"message\nwith escape");
+        let escaped = r#"msg="line1\nline2\tend"
+msg="other\nthing\there""#;
+        assert_eq!(
+            compact(escaped, &Options::default()),
+            r#"msg="line1\nline2\tend"
+msg="other\nthing\there""#
+        );
+    }
+
+    #[test]
+    fn test_crlf_line_endings() {
+        let lf = "Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root\n\
+                  Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root\n\
+                  Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root";
+        let crlf = format!("{}\r", lf.replace('\n', "\r\n"));
+
+        let opts = Options::default();
+        assert_eq!(compact(&crlf, &opts), compact(lf, &opts));
+        assert!(compact(&crlf, &opts).starts_with("[3x]"));
+    }
+
+    #[test]
+    fn test_num_width_annotation() {
+        let input = r#"session 48213 opened for user 100001
+session 51877 opened for user 23
+session 66102 opened for user 4500001"#;
+
+        let opts = Options {
+            num_width: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        // Fixed-width session column keeps its width, the varying user column doesn't
+        assert!(output.contains("[2x] session <num:5> opened for user <num>"));
+        assert!(output.contains("session <num:5> opened for user 23"));
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let rules = r#"
+[[rule]]
+name = "session"
+regex = "sess-[a-z0-9]+"
+placeholder = "<session>"
+
+[[rule]]
+name = "user"
+regex = "user=\\w+"
+placeholder = "user=<user>"
+"#;
+
+        let opts = Options {
+            rules: parse_rules(rules).unwrap(),
+            ..Options::default()
+        };
+        assert_eq!(
+            normalize_line("login sess-a81f user=alice ok", &opts),
+            "login <session> user=<user> ok"
+        );
+        assert!(parse_rules("[[rule]]\nname = \"bad\"\nregex = \"(\"\nplaceholder = \"x\"").is_err());
+    }
+
+    #[test]
+    fn test_process_id_normalization() {
+        let input = r#"Dec 10 LabSZ sshd[24245]: Connection closed by 173.234.31.186 [preauth]
+Dec 10 LabSZ sshd[1042]: Connection closed by 173.234.31.186 [preauth]"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[2x] Dec 10 LabSZ sshd[<pid>]: Connection closed by 173.234.31.186 [preauth]");
+
+        let su = normalize_line("combo su(pam_unix)[21416]: session opened", &Options::default());
+        assert_eq!(su, "combo su(pam_unix)[<pid>]: session opened");
+
+        // Indexing isn't a process ID, but PID 1 with its colon is
+        let opts = Options::default();
+        assert_eq!(normalize_line("args[0] is null", &opts), "args[0] is null");
+        assert_eq!(normalize_line("systemd[1]: Started", &opts), "systemd[<pid>]: Started");
+    }
+
+    #[test]
+    fn test_compactor_matches_batch() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 57138 ssh2"#;
+
+        let opts = Options::default();
+        let mut compactor = Compactor::new(&opts);
+        let mut lines = input.lines();
+        compactor.push_line(lines.next().unwrap());
+        compactor.push_line(lines.next().unwrap());
+        assert!(compactor.snapshot().starts_with("[2x]"));
+
+        for line in lines {
+            compactor.push_line(line);
+        }
+        assert_eq!(compactor.snapshot(), compact(input, &opts));
+    }
+
+    #[test]
+    fn test_signed_number_normalization() {
+        let input = r#"offset -123456 applied to req-48213
+offset +54087 applied to req-99120
+offset 70001 applied to req-10442"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] offset <num> applied to req-<num>");
+
+        // Short signed values and bare tree markers stay literal
+        let small = normalize_line("+   1744 delta -42 +1744", &Options::default());
+        assert_eq!(small, "+   1744 delta -42 +1744");
+    }
+
+    #[test]
+    fn test_exact_dedup() {
+        let input = r#"Connection closed by 112.95.230.3 port 54087
+Connection closed by 112.95.230.3 port 54087
+Connection closed by 112.95.230.3 port 55618
+Connection closed by 112.95.230.3 port 54087"#;
+
+        let opts = Options {
+            dedup: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        assert_eq!(
+            output,
+            "[3x] Connection closed by 112.95.230.3 port 54087\nConnection closed by 112.95.230.3 port 55618"
+        );
+    }
+
+    #[test]
+    fn test_epoch_normalization() {
+        let input = r#"heartbeat at 1702192083 from worker 17
+heartbeat at 1702192083123 from worker 17
+heartbeat at 1702195999 from worker 17"#;
+
+        let opts = Options {
+            epoch: true,
+            ..Options::default()
+        };
+        assert_eq!(compact(input, &opts), "[3x] heartbeat at <epoch> from worker 17");
+
+        // Without the flag these are plain numbers; out-of-range lengths never match
+        assert_eq!(compact(input, &Options::default()), "[3x] heartbeat at <num> from worker 17");
+        assert_eq!(normalize_line("id 17021920831", &opts), "id <num>");
+    }
+
+    #[test]
+    fn test_invert_prints_outliers() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:06 LabSZ sshd[24246]: Received disconnect from 112.95.230.3: 11: Bye Bye
+Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 57138 ssh2"#;
+
+        let opts = Options {
+            invert: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            compact(input, &opts),
+            "Dec 10 07:28:06 LabSZ sshd[24246]: Received disconnect from 112.95.230.3: 11: Bye Bye"
+        );
+    }
+
+    #[test]
+    fn test_template_ids_are_stable() {
+        let opts = Options {
+            ids: true,
+            ..Options::default()
+        };
+        let first = compact("job 48213 done\njob 51877 done", &opts);
+        let second = compact("job 99999 done\nother line\njob 12345 done\njob 10101 done", &opts);
+
+        let id = template_id("job <num> done");
+        assert_eq!(first, format!("[id={}] [2x] job <num> done", id));
+        assert!(second.starts_with(&format!("[id={}] [3x] job <num> done", id)));
+        assert_ne!(template_id("other line"), id);
+    }
+
+    #[test]
+    fn test_blank_lines_group() {
+        let input = "first event 48213\n\n   \nsecond event\n\t\nfirst event 51877";
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] <blank>\n[2x] first event <num>\nsecond event");
+    }
+
+    #[test]
+    fn test_quoted_normalization() {
+        let input = r#"level=warn msg="disk almost full" host=db1
+level=warn msg="user \"bob\" logged out" host=db1
+level=warn msg="retrying" host=db1"#;
+
+        let opts = Options {
+            normalize_quoted: true,
+            ..Options::default()
+        };
+        assert_eq!(compact(input, &opts), "[3x] level=warn msg=<str> host=db1");
+        assert_eq!(normalize_line(r#"empty "" stays"#, &opts), r#"empty "" stays"#);
+    }
+
+    #[test]
+    fn test_custom_delimiters() {
+        let input = "job 48213 started at 07:28:03 by sshd[24245]\njob 51877 started at 07:28:09 by sshd[1042]";
+
+        let opts = Options {
+            num_width: true,
+            delimiters: Delimiters {
+                open: "{".to_string(),
+                close: "}".to_string(),
+            },
+            ..Options::default()
+        };
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] job {num:5} started at {time} by sshd[{pid}]"
+        );
+    }
+
+    #[test]
+    fn test_semver_normalization() {
+        let input = r#"upgrading agent to 1.2.3
+upgrading agent to v10.4.0-rc1
+upgrading agent to 2.0.0+build.7"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] upgrading agent to <version>");
+
+        // IPv4 addresses are not versions
+        let ip = normalize_line("from 112.95.230.3 port 22", &Options::default());
+        assert_eq!(ip, "from 112.95.230.3 port 22");
+    }
+
+    #[test]
+    fn test_literal_prefix() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root port 54087
+Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root port 55618
+Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root port 57138"#;
+
+        let opts = Options {
+            literal_prefix: 3,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        assert_eq!(
+            output,
+            "[2x] Dec 10 07:28:03 LabSZ sshd[<pid>]: Failed password for root port <num>\n\
+             Dec 10 07:28:08 LabSZ sshd[<pid>]: Failed password for root port <num>"
+        );
+        assert_eq!(split_after_fields("  a  b c", 2), ("  a  b", " c"));
+        assert_eq!(split_after_fields("a b", 5), ("a b", ""));
+    }
+
+    #[test]
+    fn test_base64_normalization() {
+        let input = r#"auth cookie=dGhpcyBpcyBhIHRlc3Q= accepted
+auth cookie=c2Vzc2lvbi1pZC00MjQy accepted
+auth cookie=YW5vdGhlciB0b2tlbiB2YWx1ZQ== accepted"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] auth cookie=<base64> accepted");
+
+        // Ordinary long words, paths and symbols are left alone
+        let plain = "internationalization /System/Library/Frameworks _ZN4core3fmt5write17hAbCdE";
+        assert_eq!(normalize_line(plain, &Options::default()), plain);
+    }
+
+    #[test]
+    fn test_kv_format_round_trips() {
+        let input = "Jun 15 04:06:18 combo su(pam_unix)[21416]: session opened for user cyrus by (uid=0)\n\
+                     Jun 15 04:06:19 combo su(pam_unix)[21416]: session closed for user cyrus\n\
+                     Jun 15 04:10:22 combo su(pam_unix)[25178]: session opened for user cyrus by (uid=0)\n\
+                     Jun 15 04:10:23 combo su(pam_unix)[25178]: session closed for user cyrus\n\
+                     odd\tline with a \\ backslash";
+
+        let opts = Options {
+            format: Format::Kv,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+
+        let unescape = |value: &str| {
+            let mut result = String::new();
+            let mut chars = value.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    result.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('t') => result.push('\t'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some(other) => result.push(other),
+                    None => {}
+                }
+            }
+            result
+        };
+
+        let rows: Vec<(String, String)> = output
+            .lines()
+            .map(|line| {
+                let fields: Vec<&str> = line.split('\t').collect();
+                assert_eq!(fields.len(), 2);
+                let count = fields[0].strip_prefix("count=").unwrap().to_string();
+                let template = unescape(fields[1].strip_prefix("template=").unwrap());
+                (count, template)
+            })
+            .collect();
+
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "2".to_string(),
+                    "Jun 15 <time> combo su(pam_unix)[<pid>]: session closed for user cyrus".to_string()
+                ),
+                (
+                    "2".to_string(),
+                    "Jun 15 <time> combo su(pam_unix)[<pid>]: session opened for user cyrus by (uid=0)"
+                        .to_string()
+                ),
+                ("1".to_string(), "odd\tline with a \\ backslash".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_gzip_input() {
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let plain = "Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root port 54087\n\
+                     Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root port 55618\n";
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(plain.as_bytes()).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let decoded = read_input(&compressed[..], true, None).unwrap();
+        assert_eq!(decoded, plain);
+
+        let opts = Options::default();
+        let uncompressed = read_input(plain.as_bytes(), false, None).unwrap();
+        assert_eq!(compact(&decoded, &opts), compact(&uncompressed, &opts));
+    }
+
+    #[test]
+    fn test_max_line_length() {
+        let blob_a = "A1b2".repeat(250_000);
+        let blob_b = "Z9y8".repeat(300_000);
+        let input = format!("upload payload {}\nupload payload {}\nshort line", blob_a, blob_b);
+
+        let opts = Options {
+            max_line_length: Some(31),
+            ..Options::default()
+        };
+        let output = compact(&input, &opts);
+        assert_eq!(output, "[2x] upload payload <base64> <truncated>\nshort line");
+    }
+
+    #[test]
+    fn test_duration_normalization() {
+        let input = r#"GET /health took 123ms
+GET /health took 1.5s
+GET /health took 2m30s
+GET /health took 850µs"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[4x] GET /health took <duration>");
+
+        // Plain numbers, sizes and words with digits are not durations
+        let plain = normalize_line("read 512 bytes (10mb) over ssh2", &Options::default());
+        assert_eq!(plain, "read 512 bytes (10mb) over ssh2");
+    }
+
+    #[test]
+    fn test_preserve_spacing() {
+        let input = r#"    +   1744 ???  (in Live)  load address 0x104fc4000 + 0x114df74  [0x106111f74]
+    +   1744 ???  (in Live)  load address 0x104fc4000 + 0x115c9c0  [0x1061209c0]
+  +   1744 ???  (in Live)  load address 0x104fc4000 + 0x1e99770  [0x106e5d770]"#;
+
+        let opts = Options {
+            preserve_spacing: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        assert_eq!(output, "[3x]     +   1744 ???  (in Live)  load address <hex> + <hex>  <addr>");
+    }
+
+    #[test]
+    fn test_directory_input() {
+        let dir = std::env::temp_dir().join(format!("comprende-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("b.log"), "job 48213 done\nstartup complete").unwrap();
+        fs::write(dir.join("a.log"), "job 51877 done\n").unwrap();
+
+        let files = dir_files(&dir).unwrap();
+        assert_eq!(files, vec![dir.join("a.log"), dir.join("b.log")]);
+        assert_eq!(glob_files(&format!("{}/*.log", dir.display())).unwrap(), files);
+
+        let input = read_files(&files, false, None).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(input, "job 51877 done\njob 48213 done\nstartup complete\n");
+        assert_eq!(compact(&input, &Options::default()), "[2x] job <num> done\nstartup complete");
+    }
+
+    #[test]
+    fn test_money_normalization() {
+        let input = r#"charged card $1,234.56 for order
+charged card €99.00 for order
+charged card USD 42.00 for order
+charged card 15,000 EUR for order"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[4x] charged card <money> for order");
+
+        // Amount-free mentions of a currency stay literal
+        let plain = normalize_line("settled in USD after 3 retries", &Options::default());
+        assert_eq!(plain, "settled in USD after 3 retries");
+    }
+
+    #[test]
+    fn test_spacing_never_splits_groups() {
+        let input = "GET /index  200\t 48213\nGET /index 200 51877\nGET\t/index\t\t200\t66102 \nGET /index 404 51877";
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[3x] GET /index  200\t <num>\nGET /index 404 <num>");
+    }
+
+    #[test]
+    fn test_example_lines() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2"#;
+
+        let opts = Options {
+            example: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        let example = output.lines().nth(1).unwrap();
+        assert_eq!(example.strip_prefix("    "), input.lines().next());
+    }
+
+    #[test]
+    fn test_wrapped_hex_normalization() {
+        let input = r#"frame (0x106111f74) in Live
+frame (0x1061209c0) in Live
+frame {0x106e5d770} in Live
+frame [0x106e5d770] in Live"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(
+            output,
+            "[2x] frame (<hex>) in Live\nframe <addr> in Live\nframe {<hex>} in Live"
+        );
+
+        // Mismatched wrappers are not addresses
+        let odd = normalize_line("frame (0x1a2b] in Live", &Options::default());
+        assert_eq!(odd, "frame (<hex>] in Live");
+    }
+
+    #[test]
+    fn test_line_range() {
+        let input = "job 10001 done\njob 10002 done\nstartup complete\njob 10003 done\njob 10004 done";
+
+        let opts = Options {
+            from: Some(2),
+            to: Some(4),
+            ..Options::default()
+        };
+        assert_eq!(compact(input, &opts), "[2x] job <num> done\nstartup complete");
+
+        let tail = Options {
+            from: Some(4),
+            ..Options::default()
+        };
+        assert_eq!(compact(input, &tail), "[2x] job <num> done");
+        assert_eq!(compact(input, &Options::default()), "[4x] job <num> done\nstartup complete");
+    }
+
+    #[test]
+    fn test_all_numbers_variable() {
+        let input = "cpu=42 mem=17 load -3\ncpu=97 mem=64 load +12\ncpu=5 mem=8 load 0";
+
+        let opts = Options {
+            all_numbers_variable: true,
+            ..Options::default()
+        };
+        assert_eq!(compact(input, &opts), "[3x] cpu=<num> mem=<num> load <num>");
+        assert_eq!(compact(input, &Options::default()).lines().count(), 3);
+        // Digits inside words are not numbers
+        assert_eq!(normalize_line("via ssh2 on x86", &opts), "via ssh2 on x86");
+    }
+
+    #[test]
+    fn test_hash_normalization() {
+        let input = r#"verified file sha256=9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08
+verified file sha256=60303ae22b998861bce3b28f33eec1be758a213c86c93c076dbe9f558c11c752"#;
+
+        let output = compact(input, &Options::default());
+        assert_eq!(output, "[2x] verified file sha256=<sha256>");
+
+        let opts = Options::default();
+        assert_eq!(normalize_line("md5 d41d8cd98f00b204e9800998ecf8427e", &opts), "md5 <md5>");
+        assert_eq!(normalize_line("sha1 da39a3ee5e6b4b0d3255bfef95601890afd80709", &opts), "sha1 <sha1>");
+        // Short or odd-length hex stays as it was
+        assert_eq!(normalize_line("commit 1a2b3c4 on deadbeef", &opts), "commit 1a2b3c4 on deadbeef");
+    }
+
+    #[test]
+    fn test_metrics_block() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 57138 ssh2"#;
+
+        let opts = Options {
+            metrics: true,
+            ..Options::default()
+        };
+        let output = compact(input, &opts);
+        let metrics: Vec<&str> = output.lines().skip_while(|l| *l != "=== Metrics ===").collect();
+        assert_eq!(
+            metrics,
+            vec![
+                "=== Metrics ===",
+                "reduction ratio: 3.00",
+                "templates: 1",
+                "avg variables per template: 3.00",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_ansi() {
+        let plain = "ERROR [db] connection lost after 30001 retries\nERROR [db] connection lost after 30002 retries";
+        let colored = "\x1b[31mERROR\x1b[0m [db] connection lost after \x1b[1;33m30001\x1b[0m retries\n\
+                       \x1b[31mERROR\x1b[0m [db] connection lost after \x1b[1;33m30002\x1b[0m retries";
+
+        let opts = Options {
+            strip_ansi: true,
+            ..Options::default()
+        };
+        assert_eq!(compact(colored, &opts), compact(plain, &opts));
+        assert_eq!(compact(colored, &opts), "[2x] ERROR [db] connection lost after <num> retries");
+    }
+
+    #[test]
+    fn test_count_only() {
+        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
+Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
+Dec 10 07:28:08 LabSZ sshd[24246]: Connection closed by 112.95.230.3 [preauth]
+Dec 10 07:29:01 combo su(pam_unix)[21416]: session opened for user cyrus by (uid=0)
+Dec 10 07:29:02 combo su(pam_unix)[21416]: session closed for user cyrus
+Dec 10 07:30:01 combo su(pam_unix)[21417]: session opened for user cyrus by (uid=0)"#;
+
+        let opts = Options::default().with_count_only(true);
+        assert_eq!(compact(input, &opts), "4");
+        assert_eq!(compact("", &opts), "0");
+    }
+
+    #[test]
+    fn test_replacement_table() {
+        let table = "# known hosts\nLabSZ <host>\n\ncombo <host>\n";
+        let opts = Options::default().with_replacements(parse_replacements(table).unwrap());
+
+        let input = "Dec 10 LabSZ sshd[24245]: Connection closed\nDec 10 combo sshd[1042]: Connection closed\nDec 10 other sshd[1042]: Connection closed";
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] Dec 10 <host> sshd[<pid>]: Connection closed\nDec 10 other sshd[<pid>]: Connection closed"
+        );
+        // Only whole fields are replaced
+        assert_eq!(normalize_line("LabSZ-backup", &opts), "LabSZ-backup");
+        assert!(parse_replacements("just-one-field").is_err());
+    }
+
+    #[test]
+    fn test_labeled_thread_ids() {
+        let input = "[thread-42] INFO worker started\n[thread-7] INFO worker started\ngoroutine 1234 [running]:\ngoroutine 17 [running]:";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] [thread-<tid>] INFO worker started\n[2x] goroutine <tid> [running]:"
+        );
+    }
+
+    #[test]
+    fn test_phone_numbers() {
+        let input = "call from +1-555-123-4567 ended\ncall from (555) 987-6543 ended\ncall from 555.201.8750 ended\ncall from 10.0.0.1 ended";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[3x] call from <phone> ended\ncall from 10.0.0.1 ended"
+        );
+
+        // Bare E.164 numbers need a label; without one they are signed numbers
+        let labeled = normalize_line("sms to tel:+442071838750 sent", &Options::default());
+        assert_eq!(labeled, "sms to tel:<phone> sent");
+        let unlabeled = "offset +12345678 applied\noffset -12345678 applied";
+        assert_eq!(compact(unlabeled, &Options::default()), "[2x] offset <num> applied");
+        // Separator-less national numbers can't be told from counters
+        assert_eq!(normalize_line("call 5551234567", &Options::default()), "call <num>");
+        let epoch = normalize_line("ts +1702192083", &Options::default().with_epoch(true));
+        assert_eq!(epoch, "ts +<epoch>");
+    }
+
+    #[test]
+    fn test_path_depth() {
+        let opts = Options::default().with_path_depth(2);
+        let input = "GET /api/v2/users/12345 200\nGET /api/v2/orders/9/items 200\nGET https://example.com/api/v2/users/7 200\nGET /health 200";
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] GET /api/v2/<path-tail> 200\nGET /health 200\nGET https://example.com/api/v2/<path-tail> 200"
+        );
+    }
+
+    #[test]
+    fn test_sort_by_template() {
+        let input = "zebra\nmango\nmango\napple";
+        let opts = Options::default().with_sort(SortKey::Template);
+        assert_eq!(compact(input, &opts), "apple\n[2x] mango\nzebra");
+        assert_eq!(compact(input, &opts.with_reverse(true)), "zebra\n[2x] mango\napple");
+    }
+
+    #[test]
+    fn test_line_numbers() {
+        let input = "Jun 14 15:16:01 combo sshd(pam_unix)[19939]: check pass; user unknown\nJun 14 15:16:02 combo su(pam_unix)[19937]: session opened for user news by (uid=0)\nJun 14 15:16:02 combo su(pam_unix)[19937]: session opened for user news by (uid=0)\nJun 14 15:16:03 combo sshd(pam_unix)[19940]: check pass; user unknown\nJun 14 15:16:04 combo su(pam_unix)[19941]: session opened for user news by (uid=0)";
+        let opts = Options::default().with_line_numbers(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[3x] Jun 14 <time> combo su(pam_unix)[<pid>]: session opened for user news by (uid=0)\n    lines: 2-3, 5\n[2x] Jun 14 <time> combo sshd(pam_unix)[<pid>]: check pass; user unknown\n    lines: 1, 4"
+        );
+        // Line numbers stay those of the source when skipping ahead
+        let from = Options::default().with_line_numbers(true).with_from(4);
+        assert!(compact(input, &from).contains("check pass; user unknown\n    lines: 4\n"));
+    }
+
+    #[test]
+    fn test_kv_colon() {
+        let input = "07:28:03 level: info, msg: \"connection reset\", code: 111\n07:28:04 level: warn, msg: \"peer \\\"gone\\\"\", code: 104";
+        let opts = Options::default().with_kv_colon(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] <time> level: <val>, msg: <val>, code: <val>"
+        );
+
+        // Only the first word after a label is a value; the rest of the message stays literal
+        let labels = "host kernel: Out of memory\nhost kernel: usb 1-1 disconnected";
+        assert_eq!(
+            compact(labels, &opts),
+            "host kernel: <val> 1-1 disconnected\nhost kernel: <val> of memory"
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let input = "connect from 10.0.0.1 port 22 by jdoe@example.com\nconnect from 10.0.0.2 port 22 by root@example.com";
+        let opts = Options::default()
+            .with_redact(vec![Sensitive::Ip, Sensitive::Email])
+            .with_example(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] connect from <redacted> port 22 by <redacted>\n    connect from <redacted> port 22 by <redacted>"
+        );
+    }
+
+    #[test]
+    fn test_bars() {
+        let input = "ping\nping\nping\nping\npong\npong\npeng";
+        let opts = Options::default().with_bars(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[4x] ping ████████████████████\n[2x] pong ██████████\npeng █████"
+        );
+    }
+
+    #[test]
+    fn test_normalize_arrays() {
+        let input = "weights [1, 2, 3] ok\nweights {10 20 30 40} ok\nweights [[1,2],[3,4]] ok\nweights [1, 2} ok\nweights [7] ok";
+        let opts = Options::default().with_normalize_arrays(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[3x] weights <array> ok\nweights [1, 2} ok\nweights [7] ok"
+        );
+
+        // Lists inside broken or single-element lists still collapse
+        let nested = normalize_line("w [[1, 2]] [x [3, 4] [5 6] y] [[1 2] [3 4]", &opts);
+        assert_eq!(nested, "w [<array>] [x <array> <array> y] [<array> <array>");
+
+        // Deep or unbalanced nesting is scanned in linear time without recursion
+        let deep = format!("x {}", "[".repeat(200_000));
+        assert_eq!(normalize_line(&deep, &opts), deep);
+        let unbalanced = format!("x {}1, 2{}", "[(".repeat(50_000), "]".repeat(100));
+        assert_eq!(normalize_line(&unbalanced, &opts), unbalanced);
+    }
+
+    #[test]
+    fn test_grouped_numbers_and_ordinals() {
+        let input = "processed 1,234,567 rows on 3rd attempt\nprocessed 98,765 rows on 21st attempt\nprocessed 1,234 rows on 2nd attempt";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] processed <num> rows on <ordinal> attempt\nprocessed 1,234 rows on <ordinal> attempt"
+        );
+    }
+
+    #[test]
+    fn test_matching_template() {
+        let input = "job 123456 started\njob 654321 started\njob 123456 failed\nshutdown";
+        let opts = Options::default().with_matching("job <num> failed").with_example(true);
+        assert_eq!(compact(input, &opts), "job <num> failed\n    job 123456 failed");
+        assert_eq!(compact(input, &Options::default().with_matching("job")), "");
+    }
+
+    #[test]
+    fn test_pods_and_containers() {
+        let input = "pod/app-7d9f8b6c4-xk2lm restarted container docker://4f66ad9a0b2e8c1f3a7d5b9c0e2f4a6b8d0c1e3f5a7b9d2c4e6f8a0b1c3d5e7f\npod/app-5c8b9d7f6-q2wzt restarted container docker://9a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f0\npod/worker-6b7c8d9f4-pl4kz restarted container docker://0123456789ab";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] pod/app-<pod> restarted container docker://<container>\npod/worker-<pod> restarted container docker://<container>"
+        );
+    }
+
+    #[test]
+    fn test_date_format() {
+        let input = "14/06/2024 15:16 backup done\n02/01/2025 09:05 backup done\n31/02/2024 15:16 backup done";
+        let opts = Options::default().with_date_format("%d/%m/%Y %H:%M".parse().unwrap());
+        // February 31st only looks like a date
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] <time> backup done\n31/02/2024 15:16 backup done"
+        );
+        assert!("%Q".parse::<DateFormat>().is_err());
+
+        // Month names parse in the chosen language, and stay literal elsewhere
+        let format: DateFormat = "%d %b %Y".parse().unwrap();
+        let german = "14 Dez 2024 backup done\n3 März 2025 backup done";
+        let opts = Options::default().with_date_format(format.clone().with_locale(DateLocale::De));
+        assert_eq!(compact(german, &opts), "[2x] <time> backup done");
+        let opts = Options::default().with_date_format(format);
+        assert_eq!(normalize_line("14 Dez 2024 done", &opts), "14 Dez 2024 done");
+        assert!("xx".parse::<DateLocale>().is_err());
+
+        // Formats may open with punctuation, like Apache's bracketed timestamps
+        let apache: DateFormat = "[%d/%b/%Y:%H:%M:%S %z]".parse().unwrap();
+        let opts = Options::default().with_date_format(apache);
+        let line = "10.0.0.1 - - [10/Oct/2000:13:55:36 -0700] \"GET /\"";
+        assert_eq!(normalize_line(line, &opts), "10.0.0.1 - - <time> \"GET /\"");
+    }
+
+    #[test]
+    fn test_rule_set_reuse() {
+        let session = Regex::new(r"sess-[a-z0-9]+").unwrap();
+        let rules = RuleSet::new(vec![(session, "<session>".to_string())]);
+        assert_eq!(rules.apply("open sess-a81f"), "open <session>");
+
+        // Compiled once, shared by several option sets and calls
+        let opts = Options::default().with_rules(rules.clone());
+        assert_eq!(compact("open sess-a81f\nopen sess-9c", &opts), "[2x] open <session>");
+        let kv = Options::default().with_rules(rules).with_format(Format::Kv);
+        assert_eq!(compact("close sess-77", &kv), "count=1\ttemplate=close <session>");
+    }
+
+    #[test]
+    fn test_bracketed_levels() {
+        let input = "[INFO] cache warmed\n[ info ] cache warmed\n[ERROR] cache warmed\n(error) cache warmed\n(warn] cache warmed";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] [INFO] cache warmed\n(ERROR) cache warmed\n(warn] cache warmed\n[ERROR] cache warmed"
+        );
+    }
+
+    #[test]
+    fn test_diff() {
+        let baseline = "job 123456 started\njob 123457 started\nshutdown";
+        let candidate = "job 123456 started\nshutdown\ndisk full";
+        let diff = diff(baseline, candidate, &Options::default());
+        assert_eq!(diff.added, vec![("disk full".to_string(), 1)]);
+        assert!(diff.removed.is_empty());
+        assert_eq!(
+            diff.render(),
+            "+ [1x] disk full\n~ [2x -> 1x] job <num> started"
+        );
+    }
+
+    #[test]
+    fn test_trim_punct() {
+        let input = "Failed password for root, retrying\nFailed password for root retrying\nprotocol ssh2. done ...";
+        let opts = Options::default().with_trim_punct(true).with_example(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] Failed password for root retrying\n    Failed password for root, retrying\nprotocol ssh2 done ...\n    protocol ssh2. done ..."
+        );
+    }
+
+    #[test]
+    fn test_json_field() {
+        let input = r#"{"level":"info","message":"user 123456 logged in"}
+{"level":"info","message":"user 654321 logged in"}
+{"level":"warn","msg":"no message field"}
+not json at all"#;
+        let opts = Options::default().with_json_field("message");
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] user <num> logged in\n[2 lines without a string message field skipped]"
+        );
+
+        // Decoded newlines split the message, keeping one group per output line
+        let input = r#"{"message":"job 123456 failed\nretry"}
+{"message":"job 654321 failed\nretry"}"#;
+        assert_eq!(compact(input, &opts), "[2x] job <num> failed\n[2x] retry");
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let input = "vehicle at 37.7749,-122.4194 idle\nvehicle at -33.8688, 151.2093 idle\nvehicle at 95.1,200.5 idle";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] vehicle at <coord> idle\nvehicle at 95.1,200.5 idle"
+        );
+
+        // Comma-separated numbers aren't positions
+        let opts = Options::default();
+        let load = "load average: 0.52, 0.58, 0.59";
+        assert_eq!(normalize_line(load, &opts), load);
+        let csv = "csv 1.5,2.5,3.5 and 12.3456,45.6789,78.9012";
+        assert_eq!(normalize_line(csv, &opts), csv);
+    }
+
+    #[test]
+    fn test_file_follower() {
+        let name = format!("comprende-follow-{}.log", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, "job 123456 started\njob 1234").unwrap();
+
+        let opts = Options::default();
+        let mut compactor = Compactor::new(&opts);
+        let mut follower = FileFollower::open(&path).unwrap();
+        for line in follower.read_lines().unwrap() {
+            compactor.push_line(&line);
+        }
+        assert_eq!(compactor.snapshot(), "job <num> started");
+
+        // The writer finishes the partial line and adds another
+        let writer = path.clone();
+        std::thread::spawn(move || {
+            use std::io::Write;
+            let mut file = fs::OpenOptions::new().append(true).open(writer).unwrap();
+            file.write_all(b"57 started\njob 222222 started\n").unwrap();
+        })
+        .join()
+        .unwrap();
+        for line in follower.read_lines().unwrap() {
+            compactor.push_line(&line);
+        }
+        assert_eq!(compactor.snapshot(), "[3x] job <num> started");
+
+        // Truncation starts over from the top
+        fs::write(&path, "shutdown\n").unwrap();
+        assert_eq!(follower.read_lines().unwrap(), vec!["shutdown".to_string()]);
+
+        // Rotation to a file at least as long: the old tail, then the new file
+        #[cfg(unix)]
+        {
+            use std::io::Write;
+            fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"late").unwrap();
+            let rotated = path.with_extension("log.1");
+            fs::rename(&path, &rotated).unwrap();
+            fs::write(&path, "job 333333 started\nboot\n").unwrap();
+            assert_eq!(
+                follower.read_lines().unwrap(),
+                vec!["late".to_string(), "job 333333 started".to_string(), "boot".to_string()]
+            );
+            fs::remove_file(&rotated).unwrap();
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_brace_guids_and_event_ids() {
+        let input = "EventID=4624 logon {550e8400-e29b-41d4-a716-446655440000}\nEventID=4624 logon {6F9619FF-8B86-D011-B42D-00C04FC964FF}\nEventID=4625 logon {550e8400-e29b-41d4-a716-446655440000}";
+        let opts = Options::default().with_all_numbers_variable(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] EventID=4624 logon {<uuid>}\nEventID=4625 logon {<uuid>}"
+        );
+    }
+
+    #[test]
+    fn test_input_encoding() {
+        // "Zugriff für José verweigert" in Latin-1
+        let latin1 = b"Zugriff f\xfcr Jos\xe9 verweigert\n";
+        let decoded = read_input(&latin1[..], false, Encoding::for_label(b"latin1")).unwrap();
+        assert_eq!(decoded, "Zugriff für José verweigert\n");
+
+        // Without an encoding the bad bytes are replaced rather than fatal
+        let lossy = read_input(&latin1[..], false, None).unwrap();
+        assert_eq!(lossy, "Zugriff f\u{fffd}r Jos\u{fffd} verweigert\n");
+    }
+
+    #[test]
+    fn test_bracketed_structure_kept_around_pid() {
+        let input = "combo sshd(pam_unix)[20892]: check pass; user unknown\ncombo sshd(pam_unix)[20914]: check pass; user unknown";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] combo sshd(pam_unix)[<pid>]: check pass; user unknown"
+        );
+    }
+
+    #[test]
+    fn test_no_inherent() {
+        let input = "Dec 10 07:28:03 LabSZ sshd[24245]: Connection closed\nDec 10 07:28:04 LabSZ sshd[24245]: Connection closed";
+        let opts = Options::default().with_no_inherent(true);
+        assert_eq!(compact(input, &opts), input);
+
+        let session = Regex::new(r"sshd\[\d+\]").unwrap();
+        let opts = opts.with_rules(RuleSet::new(vec![(session, "sshd".to_string())]));
+        assert_eq!(
+            normalize_line("Dec 10 07:28:03 LabSZ sshd[24245]: closed", &opts),
+            "Dec 10 07:28:03 LabSZ sshd: closed"
+        );
+
+        // Opt-in detectors still run when asked for
+        let opts = Options::default()
+            .with_no_inherent(true)
+            .with_kv_colon(true)
+            .with_epoch(true);
+        assert_eq!(
+            normalize_line("ts 1702192083 code: 111 pid 24245", &opts),
+            "ts <epoch> code: <val> pid 24245"
+        );
+    }
+
+    #[test]
+    fn test_column_rules() {
+        let rules = vec!["2:time".parse().unwrap(), "3:user:^u-".parse().unwrap()];
+        let opts = Options::default().with_column_rules(rules);
+        let input = "srv1 0728a u-alice ok\nsrv1 1345b u-bob ok\nsrv1 1345b root ok";
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] srv1 <time> <user> ok\nsrv1 <time> root ok"
+        );
+        // User placeholders count as variables
+        let opts = opts.with_metrics(true);
+        assert!(compact(input, &opts).contains("avg variables per template: 1.50"));
+        let annotated = compact(input, &opts.with_annotate_types(true));
+        assert!(annotated.contains("[2x] srv1 <0:time> <1:user> ok\n"));
+        assert!(annotated.contains("avg variables per template: 1.50"));
+        assert!("0:time".parse::<ColumnRule>().is_err());
+        assert!("2".parse::<ColumnRule>().is_err());
+    }
+
+    #[test]
+    fn test_markdown_format() {
+        let input = "Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root port 54087\nDec 10 07:28:05 LabSZ sshd[24245]: Failed password for root port 55618\npipe | in `line`";
+        let opts = Options::default().with_format(Format::Markdown);
+        assert_eq!(
+            compact(input, &opts),
+            "| Count | Template | Sample |\n\
+             |------:|----------|--------|\n\
+             | 2 | `Dec 10 <time> LabSZ sshd[<pid>]: Failed password for root port <num>` | `Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root port 54087` |\n\
+             | 1 | `` pipe \\| in `line` `` | `` pipe \\| in `line` `` |"
+        );
+    }
+
+    #[test]
+    fn test_mixed_alphanumeric_ids() {
+        let input = "trace a1b2c3d4e5 via ssh2 done\ntrace Xk7Qp2Lm9 via ssh2 done\ntrace password via ssh2 done";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] trace <id> via ssh2 done\ntrace password via ssh2 done"
+        );
+
+        // Tokens other detectors own keep their own placeholders
+        let opts = Options::default();
+        assert_eq!(normalize_line("took 123456ms", &opts), "took <duration>");
+        assert_eq!(normalize_line("took 1h30m15s", &opts), "took <duration>");
+        assert_eq!(normalize_line("rank 100000th", &opts), "rank <ordinal>");
+        assert_eq!(
+            normalize_line("req 550e8400-e29b-41d4-a716-446655440000 done", &opts),
+            "req <uuid> done"
+        );
+        // Identifiers, tools and encodings aren't IDs
+        let named = "run sha256sum on base64url and win32api x86_64 utf8mb4 iso8601 md5sum";
+        assert_eq!(normalize_line(named, &opts), named);
+        let classes = "class Log4jConfigLoader loaded\nclass Log4jAppenderBase loaded";
+        assert_eq!(
+            compact(classes, &opts),
+            "class Log4jAppenderBase loaded\nclass Log4jConfigLoader loaded"
+        );
+        let idents = "HTTP2Server on Windows10 with libcrypto3 Vector3f Int32Array utf8ToUtf16";
+        assert_eq!(normalize_line(idents, &opts), idents);
+        // Hex and mixed-case random strings still are
+        let random = normalize_line("token 9fa3be01 kS8hGqLwzr", &opts);
+        assert_eq!(random, "token <id> <id>");
+    }
+
+    #[test]
+    fn test_pagination() {
+        let input = "a\na\na\na\nb\nb\nb\nc\nc\nd";
+        let opts = Options::default().with_page_size(2).with_page(2);
+        assert_eq!(compact(input, &opts), "[2x] c\nd\npage 2/2");
+
+        // The summary still counts every template
+        let opts = Options::default().with_page_size(1).with_summary(true);
+        assert!(compact(input, &opts).starts_with("# 10 lines -> 4 templates\n"));
+        assert_eq!(compactor_for(input, &opts).page_count(), Some(4));
+    }
+
+    #[test]
+    fn test_unescape() {
+        // Two physical lines, each carrying a two-line payload
+        let input = "job 123456 failed\\n  retrying in 5s\njob 654321 failed\\n  retrying in 10s";
+        let opts = Options::default().with_unescape(true);
+        assert_eq!(
+            compact(input, &opts),
+            "[2x] job <num> failed\n[2x] retrying in <duration>"
+        );
+        assert_eq!(unescape(r"a\tb\\n\q"), "a\tb\\n\\q");
+
+        // Line numbers count source lines, not unescaped fragments
+        let input = "a 11111\\nb\nc\nc";
+        let opts = opts.with_line_numbers(true);
+        assert!(compact(input, &opts).starts_with("[2x] c\n    lines: 2-3\n"));
+
+        // With a JSON field, the field is unescaped rather than the raw line
+        let input = r#"{"message":"job 123456 failed\\n  retry"}
+{"message":"job 654321 failed\\n  retry"}"#;
+        let opts = Options::default().with_unescape(true).with_json_field("message");
+        assert_eq!(compact(input, &opts), "[2x] job <num> failed\n[2x] retry");
+    }
+
+    #[test]
+    fn test_separator_runs() {
+        let input = "=====\n==========\n----- results -----\n-------- results --------\nwait ... ???";
+        assert_eq!(
+            compact(input, &Options::default()),
+            "[2x] <sep>\n[2x] <sep> results <sep>\nwait ... ???"
+        );
+    }
+
+    #[test]
+    fn test_annotate_types() {
+        let input = "Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 173.234.31.186 port 38926 ssh2";
+        let opts = Options::default()
+            .with_annotate_types(true)
+            .with_redact(vec![Sensitive::Ip]);
+        assert_eq!(
+            compact(input, &opts),
+            "Dec 10 <0:time> LabSZ sshd[<1:pid>]: Failed password for root from <2:redacted> port <3:num> ssh2"
+        );
+        // Literal text in placeholder-like brackets isn't a variable
+        let java = "at Foo.<init>(Foo.java:10) took 123456ms";
+        assert_eq!(compact(java, &opts), "at Foo.<init>(Foo.java:10) took <0:duration>");
+    }
+}
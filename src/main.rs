@@ -1,203 +1,383 @@
-use lazy_static::lazy_static;
-use regex::Regex;
-use std::collections::HashMap;
-use std::io::{self, Read};
-
-lazy_static! {
-    // Hex addresses like 0x104fc4000 or 0x1a377d770
-    static ref HEX_ADDR: Regex = Regex::new(r"0x[a-fA-F0-9]+").unwrap();
-    // Bracketed addresses like [0x106111f74]
-    static ref BRACKETED_HEX: Regex = Regex::new(r"\[0x[a-fA-F0-9]+\]").unwrap();
-    // UUIDs like <4B0BCBB4-2271-376E-B5C3-CC18D418FC11>
-    static ref UUID_PATTERN: Regex = Regex::new(r"<[A-F0-9]{8}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{4}-[A-F0-9]{12}>").unwrap();
-    // Thread IDs like Thread_4243153
-    static ref THREAD_ID: Regex = Regex::new(r"Thread_\d+").unwrap();
-    // Timestamps like 07:28:03 or 22:18:29.360
-    static ref TIMESTAMP: Regex = Regex::new(r"\b\d{2}:\d{2}:\d{2}(?:\.\d+)?").unwrap();
-    // Large numbers (5+ digits) that are likely variable identifiers
-    static ref LARGE_NUM: Regex = Regex::new(r"\b\d{5,}\b").unwrap();
-    // Indentation pattern: leading whitespace and tree markers
-    static ref INDENT_PATTERN: Regex = Regex::new(r"^([\s+!|:]+)").unwrap();
-    // Binary image line pattern (macOS sample/crash reports)
-    static ref BINARY_IMAGE: Regex = Regex::new(r"^\s*0x[a-fA-F0-9]+\s+-\s+0x[a-fA-F0-9]+\s+").unwrap();
-    // System library paths
-    static ref SYSTEM_LIB: Regex = Regex::new(r"/System/Library/|/usr/lib/").unwrap();
-}
-
-/// Normalize a line by replacing variable parts with placeholders
-fn normalize_line(line: &str) -> String {
-    let mut result = line.to_string();
-
-    // Replace bracketed hex addresses first (more specific)
-    result = BRACKETED_HEX.replace_all(&result, "<addr>").to_string();
-    // Replace hex addresses
-    result = HEX_ADDR.replace_all(&result, "<hex>").to_string();
-    // Replace UUIDs
-    result = UUID_PATTERN.replace_all(&result, "<uuid>").to_string();
-    // Replace thread IDs
-    result = THREAD_ID.replace_all(&result, "Thread_<id>").to_string();
-    // Replace timestamps
-    result = TIMESTAMP.replace_all(&result, "<time>").to_string();
-    // Replace large numbers (but keep small ones like line offsets)
-    result = LARGE_NUM.replace_all(&result, "<num>").to_string();
-
-    result
-}
-
-/// Normalize indentation - strip it entirely for better grouping
-fn normalize_indent(line: &str) -> String {
-    INDENT_PATTERN.replace(line, "").to_string()
-}
-
-/// Group and deduplicate lines
-struct LineGroup {
-    normalized: String,
-    count: usize,
-}
-
-fn process(input: &str) -> String {
-    let lines: Vec<&str> = input.lines().collect();
-
-    if lines.is_empty() {
-        return String::new();
-    }
-
-    // Step 1: Separate binary images from other content
-    let mut regular_lines: Vec<&str> = Vec::new();
-    let mut system_images: Vec<&str> = Vec::new();
-    let mut app_images: Vec<&str> = Vec::new();
-
-    for line in &lines {
-        if BINARY_IMAGE.is_match(line) {
-            if SYSTEM_LIB.is_match(line) {
-                system_images.push(line);
-            } else {
-                app_images.push(line);
-            }
-        } else {
-            regular_lines.push(line);
+use comprende::{
+    Compactor, Encoding, FileFollower, Options, compactor_for, diff, dir_files, glob_files,
+    parse_replacements, parse_rules, read_files, read_input,
+};
+use std::env;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "usage: comprende [OPTIONS] [PATH]";
+
+/// Why the CLI gave up: bad arguments (exit 2), failed IO, an unmatched `--match` or
+/// new templates under `--diff` (exit 1)
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Io(io::Error),
+    NoMatch(String),
+    NewTemplates(usize),
+}
+
+impl CliError {
+    fn exit_code(&self) -> ExitCode {
+        match self {
+            CliError::Usage(_) => ExitCode::from(2),
+            CliError::Io(_) | CliError::NoMatch(_) | CliError::NewTemplates(_) => ExitCode::from(1),
         }
     }
+}
 
-    // Step 2: Normalize and group regular lines
-    let mut groups: HashMap<String, LineGroup> = HashMap::new();
-
-    for line in &regular_lines {
-        // First normalize variable parts (hex, etc.)
-        let normalized = normalize_line(line);
-        // Then normalize indentation
-        let key = normalize_indent(&normalized);
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(msg) => write!(f, "{}\n{}", msg, USAGE),
+            CliError::Io(err) => write!(f, "{}", err),
+            CliError::NoMatch(template) => write!(f, "no template matches: {}", template),
+            CliError::NewTemplates(count) => write!(f, "{} new templates", count),
+        }
+    }
+}
 
-        groups
-            .entry(key.clone())
-            .and_modify(|g| g.count += 1)
-            .or_insert_with(|| LineGroup {
-                normalized: key,
-                count: 1,
-            });
+impl From<io::Error> for CliError {
+    fn from(err: io::Error) -> Self {
+        CliError::Io(err)
     }
+}
+
+fn usage_error(msg: String) -> CliError {
+    CliError::Usage(msg)
+}
+
+/// Take the value following `flag`, or fail if it's missing
+fn flag_value<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, CliError> {
+    args.next()
+        .ok_or_else(|| usage_error(format!("{} requires a value", flag)))
+}
+
+/// Take the numeric value following `flag`
+fn flag_number<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<usize, CliError> {
+    let value = flag_value(args, flag)?;
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("invalid value for {}: {}", flag, value)))
+}
 
-    // Step 3: Sort by count (descending), then alphabetically for stability
-    let mut sorted_groups: Vec<_> = groups.into_values().collect();
-    sorted_groups.sort_by(|a, b| {
-        b.count.cmp(&a.count).then_with(|| a.normalized.cmp(&b.normalized))
-    });
+/// Prefix an IO error with the path it happened on
+fn path_error(path: &str, err: io::Error) -> io::Error {
+    io::Error::new(err.kind(), format!("{}: {}", path, err))
+}
 
-    // Step 4: Format output
-    let mut output = Vec::new();
+/// Read the file named by a flag like `--rules`
+fn read_flag_file(path: &str) -> io::Result<String> {
+    fs::read_to_string(path).map_err(|err| path_error(path, err))
+}
 
-    for group in sorted_groups {
-        if group.count == 1 {
-            output.push(group.normalized);
-        } else {
-            output.push(format!("[{}x] {}", group.count, group.normalized));
+/// Parsed command line: library options plus where to read input from
+struct Cli {
+    opts: Options,
+    /// Input file or directory; stdin when absent
+    path: Option<String>,
+    /// Glob pattern selecting input files
+    glob: Option<String>,
+    /// Gunzip the input (implied for paths ending in `.gz`)
+    gzip: bool,
+    /// Encoding to transcode the input from; lossy UTF-8 when absent
+    encoding: Option<&'static Encoding>,
+    /// File to write the result to instead of stdout
+    output: Option<String>,
+    /// Baseline and candidate files to compare instead of compacting
+    diff: Option<(String, String)>,
+    /// File to keep reading as it grows, reprinting the snapshot
+    follow: Option<String>,
+    /// Seconds between `--follow` polls
+    follow_interval: u64,
+    /// Print how long each stage took to stderr
+    profile: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Cli, CliError> {
+    let mut opts = Options::default();
+    let mut path = None;
+    let mut glob = None;
+    let mut gzip = false;
+    let mut encoding = None;
+    let mut output = None;
+    let mut diff = None;
+    let mut follow = None;
+    let mut follow_interval = 2;
+    let mut date_locale = None;
+    let mut profile = false;
+    let mut args = args;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--normalize-json" => opts.normalize_json = true,
+            "--summary" => opts.summary = true,
+            "--num-width" => opts.num_width = true,
+            "--dedup" => opts.dedup = true,
+            "--epoch" => opts.epoch = true,
+            "--invert" => opts.invert = true,
+            "--ids" => opts.ids = true,
+            "--normalize-quoted" => opts.normalize_quoted = true,
+            "--rules" => {
+                let path = flag_value(&mut args, &arg)?;
+                let text = read_flag_file(&path)?;
+                opts.rules =
+                    parse_rules(&text).map_err(|err| usage_error(format!("{}: {}", path, err)))?;
+            }
+            "--var-open" | "--var-close" => {
+                let value = flag_value(&mut args, &arg)?;
+                if value.is_empty() {
+                    return Err(usage_error(format!("{} must not be empty", arg)));
+                }
+                if arg == "--var-open" {
+                    opts.delimiters.open = value;
+                } else {
+                    opts.delimiters.close = value;
+                }
+            }
+            "--literal-prefix" => opts.literal_prefix = flag_number(&mut args, &arg)?,
+            "--format" => {
+                opts.format = flag_value(&mut args, &arg)?.parse().map_err(usage_error)?;
+            }
+            "--max-line-length" => opts.max_line_length = Some(flag_number(&mut args, &arg)?),
+            "--preserve-spacing" => opts.preserve_spacing = true,
+            "--example" => opts.example = true,
+            "--from" => opts.from = Some(flag_number(&mut args, &arg)?),
+            "--to" => opts.to = Some(flag_number(&mut args, &arg)?),
+            "--all-numbers-variable" => opts.all_numbers_variable = true,
+            "--metrics" => opts.metrics = true,
+            "--strip-ansi" => opts.strip_ansi = true,
+            "--count-only" => opts.count_only = true,
+            "--replace" => {
+                let path = flag_value(&mut args, &arg)?;
+                let text = read_flag_file(&path)?;
+                opts.replacements = parse_replacements(&text)
+                    .map_err(|err| usage_error(format!("{}: {}", path, err)))?;
+            }
+            "--normalize-paths-depth" => opts.path_depth = Some(flag_number(&mut args, &arg)?),
+            "--sort" => {
+                opts.sort = flag_value(&mut args, &arg)?.parse().map_err(usage_error)?;
+            }
+            "--reverse" => opts.reverse = true,
+            "--line-numbers" => opts.line_numbers = true,
+            "--kv-colon" => opts.kv_colon = true,
+            "--redact" => {
+                opts.redact = flag_value(&mut args, &arg)?
+                    .split(',')
+                    .map(str::parse)
+                    .collect::<Result<_, _>>()
+                    .map_err(usage_error)?;
+            }
+            "--bars" => opts.bars = true,
+            "--normalize-arrays" => opts.normalize_arrays = true,
+            "--match" => opts.matching = Some(flag_value(&mut args, &arg)?),
+            "--date-format" => {
+                let format = flag_value(&mut args, &arg)?.parse().map_err(usage_error)?;
+                opts.date_format = Some(format);
+            }
+            "--trim-punct" => opts.trim_punct = true,
+            "--json-field" => opts.json_field = Some(flag_value(&mut args, &arg)?),
+            "--no-inherent" => opts.no_inherent = true,
+            "--column-rule" => {
+                let rule = flag_value(&mut args, &arg)?.parse().map_err(usage_error)?;
+                opts.column_rules.push(rule);
+            }
+            "--page" | "--page-num" => {
+                let value = flag_number(&mut args, &arg)?;
+                if value == 0 {
+                    return Err(usage_error(format!("{} must be at least 1", arg)));
+                }
+                if arg == "--page" {
+                    opts.page_size = Some(value);
+                } else {
+                    opts.page = value;
+                }
+            }
+            "--unescape" => opts.unescape = true,
+            "--profile" => profile = true,
+            "--annotate-types" => opts.annotate_types = true,
+            "--date-locale" => {
+                date_locale = Some(flag_value(&mut args, &arg)?.parse().map_err(usage_error)?);
+            }
+            "--gzip" => gzip = true,
+            "--input-encoding" => {
+                let label = flag_value(&mut args, &arg)?;
+                let found = Encoding::for_label(label.as_bytes());
+                encoding =
+                    Some(found.ok_or_else(|| usage_error(format!("unknown encoding: {}", label)))?);
+            }
+            "--glob" => glob = Some(flag_value(&mut args, &arg)?),
+            "--output" => output = Some(flag_value(&mut args, &arg)?),
+            "--follow" => follow = Some(flag_value(&mut args, &arg)?),
+            "--follow-interval" => follow_interval = flag_number(&mut args, &arg)? as u64,
+            "--diff" => {
+                let baseline = flag_value(&mut args, &arg)?;
+                diff = Some((baseline, flag_value(&mut args, &arg)?));
+            }
+            _ if !arg.starts_with("--") && path.is_none() => path = Some(arg),
+            _ => return Err(usage_error(format!("unknown argument: {}", arg))),
         }
     }
 
-    // Step 5: Add binary images summary
-    if !system_images.is_empty() || !app_images.is_empty() {
-        output.push(String::new());
-        output.push("=== Binary Images ===".to_string());
+    // Following and diffing don't run the read/group/format stages, and
+    // following never finishes an output to write
+    if profile && (follow.is_some() || diff.is_some()) {
+        return Err(usage_error(
+            "--profile can't be used with --follow or --diff".to_string(),
+        ));
+    }
+    if output.is_some() && follow.is_some() {
+        return Err(usage_error(
+            "--output can't be used with --follow".to_string(),
+        ));
+    }
 
-        // Keep app/plugin images (they're relevant for debugging)
-        for img in &app_images {
-            output.push(normalize_line(img));
-        }
+    // Widening the built-in number pattern means nothing once it's off
+    if opts.no_inherent && opts.all_numbers_variable {
+        return Err(usage_error(
+            "--all-numbers-variable has no effect with --no-inherent".to_string(),
+        ));
+    }
 
-        // Summarize system images
-        if !system_images.is_empty() {
-            output.push(format!("[{} system libraries omitted]", system_images.len()));
-        }
+    // Month names are only looked up in a custom format
+    if let Some(locale) = date_locale {
+        let format = opts
+            .date_format
+            .take()
+            .ok_or_else(|| usage_error("--date-locale requires --date-format".to_string()))?;
+        opts.date_format = Some(format.with_locale(locale));
     }
 
-    output.join("\n")
+    Ok(Cli {
+        opts,
+        path,
+        glob,
+        gzip,
+        encoding,
+        output,
+        diff,
+        follow,
+        follow_interval,
+        profile,
+    })
 }
 
-fn main() -> io::Result<()> {
-    let mut input = String::new();
-    io::stdin().read_to_string(&mut input)?;
-
-    let output = process(&input);
+/// Create or truncate `path` and write `output` to it, newline-terminated
+fn write_output(path: &str, output: &str) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
     if !output.is_empty() {
-        println!("{}", output);
+        writeln!(file, "{}", output)?;
     }
+    file.flush()
+}
 
-    Ok(())
+/// Print `result`, or write it to the `--output` file when one was given
+fn write_result(output: Option<&str>, result: &str) -> io::Result<()> {
+    match output {
+        Some(path) => write_output(path, result)
+            .map_err(|err| io::Error::new(err.kind(), format!("cannot write {}: {}", path, err))),
+        None if !result.is_empty() => {
+            println!("{}", result);
+            Ok(())
+        }
+        None => Ok(()),
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Read a whole input file, gunzipping `.gz` files
+fn read_path(path: &str, cli: &Cli) -> io::Result<String> {
+    let file = File::open(path).map_err(|err| path_error(path, err))?;
+    read_input(file, cli.gzip || path.ends_with(".gz"), cli.encoding)
+        .map_err(|err| path_error(path, err))
+}
 
-    #[test]
-    fn test_hex_normalization() {
-        let input = "+   1744 ???  (in Live)  load address 0x104fc4000 + 0x115bc98  [0x10611fc98]";
-        let normalized = normalize_line(input);
-        assert_eq!(normalized, "+   1744 ???  (in Live)  load address <hex> + <hex>  <addr>");
-    }
+/// Print the snapshot of `path` every `interval` seconds that new lines arrived, forever
+fn follow(path: &str, opts: &Options, interval: u64) -> io::Result<()> {
+    let mut follower = FileFollower::open(Path::new(path)).map_err(|err| path_error(path, err))?;
+    let mut compactor = Compactor::new(opts);
 
-    #[test]
-    fn test_indent_normalization() {
-        let input = "+   1744 ???  (in Live)  load address <hex> + <hex>  <addr>";
-        let result = normalize_indent(input);
-        assert_eq!(result, "1744 ???  (in Live)  load address <hex> + <hex>  <addr>");
+    loop {
+        let lines = follower.read_lines().map_err(|err| path_error(path, err))?;
+        if !lines.is_empty() {
+            for line in &lines {
+                compactor.push_line(line);
+            }
+            println!("{}\n", compactor.snapshot());
+        }
+        thread::sleep(Duration::from_secs(interval));
     }
+}
 
-    #[test]
-    fn test_thread_id_normalization() {
-        let input = "1744 Thread_4243153   DispatchQueue_1: com.apple.main-thread";
-        let normalized = normalize_line(input);
-        assert_eq!(normalized, "1744 Thread_<id>   DispatchQueue_1: com.apple.main-thread");
-    }
+fn run() -> Result<(), CliError> {
+    let cli = parse_args(env::args().skip(1))?;
 
-    #[test]
-    fn test_uuid_normalization() {
-        let input = "<4B0BCBB4-2271-376E-B5C3-CC18D418FC11> /System/Library/foo";
-        let normalized = normalize_line(input);
-        assert_eq!(normalized, "<uuid> /System/Library/foo");
+    if let Some(path) = &cli.follow {
+        return Ok(follow(path, &cli.opts, cli.follow_interval)?);
     }
 
-    #[test]
-    fn test_stack_trace_dedup() {
-        let input = r#"+   1744 ???  (in Live)  load address 0x104fc4000 + 0x114df74  [0x106111f74]
-+   1744 ???  (in Live)  load address 0x104fc4000 + 0x115c9c0  [0x1061209c0]
-+   1744 ???  (in Live)  load address 0x104fc4000 + 0x1e99770  [0x106e5d770]"#;
+    if let Some((baseline, candidate)) = &cli.diff {
+        let baseline = read_path(baseline, &cli)?;
+        let candidate = read_path(candidate, &cli)?;
+        let diff = diff(&baseline, &candidate, &cli.opts);
+        let rendered = diff.render();
+        write_result(cli.output.as_deref(), &rendered)?;
+        if !diff.added.is_empty() {
+            return Err(CliError::NewTemplates(diff.added.len()));
+        }
+        return Ok(());
+    }
 
-        let output = process(input);
-        // All three lines should be deduped into one with count 3
-        assert!(output.contains("[3x]"));
-        assert!(output.contains("(in Live)"));
+    let start = Instant::now();
+    let input = match (&cli.glob, &cli.path) {
+        (Some(pattern), _) => read_files(&glob_files(pattern)?, cli.gzip, cli.encoding)?,
+        (None, Some(path)) if Path::new(path).is_dir() => {
+            read_files(&dir_files(Path::new(path))?, cli.gzip, cli.encoding)?
+        }
+        (None, Some(path)) => read_path(path, &cli)?,
+        (None, None) => read_input(io::stdin().lock(), cli.gzip, cli.encoding)?,
+    };
+
+    let read = Instant::now();
+    let compactor = compactor_for(&input, &cli.opts);
+    let grouped = Instant::now();
+    // A page past the end is a mistake, not an empty result
+    if let Some(pages) = compactor.page_count()
+        && cli.opts.page > pages.max(1)
+    {
+        return Err(usage_error(format!(
+            "--page-num {} is past the last page ({})",
+            cli.opts.page,
+            pages.max(1)
+        )));
+    }
+    let output = compactor.snapshot();
+    if cli.profile {
+        eprintln!("profile: reading {:?}", read - start);
+        eprintln!("profile: grouping {:?}", grouped - read);
+        eprintln!("profile: formatting {:?}", grouped.elapsed());
+    }
+    if let Some(template) = &cli.opts.matching
+        && output.is_empty()
+    {
+        return Err(CliError::NoMatch(template.clone()));
     }
+    write_result(cli.output.as_deref(), &output)?;
 
-    #[test]
-    fn test_sshd_logs() {
-        let input = r#"Dec 10 07:28:03 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 54087 ssh2
-Dec 10 07:28:05 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 55618 ssh2
-Dec 10 07:28:08 LabSZ sshd[24245]: Failed password for root from 112.95.230.3 port 57138 ssh2"#;
+    Ok(())
+}
 
-        let output = process(input);
-        // All three lines should be deduped (port numbers normalized as large nums)
-        assert!(output.contains("[3x]"));
-        assert!(output.contains("Failed password"));
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("comprende: {}", err);
+            err.exit_code()
+        }
     }
 }
@@ -0,0 +1,237 @@
+use assert_cmd::Command;
+use std::env;
+use std::fs;
+
+#[test]
+fn test_unknown_flag_is_usage_error() {
+    let output = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--bogus")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("unknown argument: --bogus"));
+    assert!(stderr.contains("usage: comprende"));
+}
+
+#[test]
+fn test_output_file() {
+    let path = env::temp_dir().join(format!("comprende-output-{}.txt", std::process::id()));
+
+    Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--output")
+        .arg(&path)
+        .write_stdin("ping\nping\npong\n")
+        .assert()
+        .success()
+        .stdout("");
+
+    let written = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(written, "[2x] ping\npong\n");
+}
+
+#[test]
+fn test_unmatched_template_fails() {
+    let output = Command::cargo_bin("comprende")
+        .unwrap()
+        .args(["--match", "nope"])
+        .write_stdin("ping\n")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("no template matches: nope"));
+}
+
+#[test]
+fn test_diff_fails_on_new_templates() {
+    let dir = env::temp_dir();
+    let baseline = dir.join(format!("comprende-baseline-{}.log", std::process::id()));
+    let candidate = dir.join(format!("comprende-candidate-{}.log", std::process::id()));
+    fs::write(&baseline, "job 123456 started\nshutdown\n").unwrap();
+    fs::write(&candidate, "job 654321 started\nshutdown\ndisk full\n").unwrap();
+
+    let output = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--diff")
+        .args([&baseline, &candidate])
+        .output()
+        .unwrap();
+
+    // --output applies to the diff too
+    let written = dir.join(format!("comprende-diff-{}.txt", std::process::id()));
+    let to_file = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--diff")
+        .args([&baseline, &candidate])
+        .arg("--output")
+        .arg(&written)
+        .output()
+        .unwrap();
+    fs::remove_file(&baseline).unwrap();
+    fs::remove_file(&candidate).unwrap();
+    assert_eq!(to_file.status.code(), Some(1));
+    assert!(to_file.stdout.is_empty());
+    assert_eq!(fs::read_to_string(&written).unwrap(), "+ [1x] disk full\n");
+    fs::remove_file(&written).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "+ [1x] disk full\n"
+    );
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("1 new templates")
+    );
+}
+
+#[test]
+fn test_profile_goes_to_stderr() {
+    let input = "ping\nping\npong\n";
+    let plain = Command::cargo_bin("comprende")
+        .unwrap()
+        .write_stdin(input)
+        .output()
+        .unwrap();
+    let profiled = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--profile")
+        .write_stdin(input)
+        .output()
+        .unwrap();
+
+    assert_eq!(profiled.stdout, plain.stdout);
+    let stderr = String::from_utf8(profiled.stderr).unwrap();
+    for stage in ["reading", "grouping", "formatting"] {
+        assert!(stderr.contains(&format!("profile: {} ", stage)));
+    }
+}
+
+#[test]
+fn test_rules_file_errors() {
+    let missing = Command::cargo_bin("comprende")
+        .unwrap()
+        .args(["--rules", "/nonexistent/rules.toml"])
+        .output()
+        .unwrap();
+    assert_eq!(missing.status.code(), Some(1));
+    let stderr = String::from_utf8(missing.stderr).unwrap();
+    assert!(stderr.contains("/nonexistent/rules.toml"));
+
+    // A bad regex is a bad argument, like a bad --column-rule
+    let path = env::temp_dir().join(format!("comprende-rules-{}.toml", std::process::id()));
+    fs::write(
+        &path,
+        "[[rule]]\nname = \"bad\"\nregex = \"(\"\nplaceholder = \"x\"\n",
+    )
+    .unwrap();
+    let invalid = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg("--rules")
+        .arg(&path)
+        .output()
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(invalid.status.code(), Some(2));
+    let stderr = String::from_utf8(invalid.stderr).unwrap();
+    assert!(stderr.contains("invalid regex in rule bad"));
+}
+
+#[test]
+fn test_bad_pages_are_usage_errors() {
+    for args in [
+        &["--page", "0"][..],
+        &["--page", "1", "--page-num", "0"],
+        &["--page", "1", "--page-num", "9"],
+    ] {
+        let output = Command::cargo_bin("comprende")
+            .unwrap()
+            .args(args)
+            .write_stdin("a\na\nb\n")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+
+    let stderr = Command::cargo_bin("comprende")
+        .unwrap()
+        .args(["--page", "1", "--page-num", "9"])
+        .write_stdin("a\na\nb\n")
+        .output()
+        .unwrap()
+        .stderr;
+    assert!(
+        String::from_utf8(stderr)
+            .unwrap()
+            .contains("--page-num 9 is past the last page (2)")
+    );
+}
+
+#[test]
+fn test_input_errors_name_the_file() {
+    let dir = env::temp_dir().join(format!("comprende-corrupt-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("bad.gz"), b"\x1f\x8b\x08\x00").unwrap();
+    let corrupt = Command::cargo_bin("comprende")
+        .unwrap()
+        .arg(&dir)
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(corrupt.status.code(), Some(1));
+    assert!(
+        String::from_utf8(corrupt.stderr)
+            .unwrap()
+            .contains("bad.gz: ")
+    );
+
+    let missing = Command::cargo_bin("comprende")
+        .unwrap()
+        .args(["--follow", "/nonexistent/follow.log"])
+        .output()
+        .unwrap();
+    assert_eq!(missing.status.code(), Some(1));
+    assert!(
+        String::from_utf8(missing.stderr)
+            .unwrap()
+            .contains("/nonexistent/follow.log: ")
+    );
+}
+
+#[test]
+fn test_empty_delimiters_are_usage_errors() {
+    for flag in ["--var-open", "--var-close"] {
+        let output = Command::cargo_bin("comprende")
+            .unwrap()
+            .args([flag, ""])
+            .write_stdin("runtime 123456\n")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2));
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(&format!("{} must not be empty", flag)));
+    }
+}
+
+#[test]
+fn test_modes_reject_unused_flags() {
+    for args in [
+        &["--follow", "app.log", "--profile"][..],
+        &["--follow", "app.log", "--output", "out.txt"],
+        &["--diff", "a.log", "b.log", "--profile"],
+    ] {
+        let output = Command::cargo_bin("comprende")
+            .unwrap()
+            .args(args)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+    }
+}